@@ -0,0 +1,90 @@
+//! Counts the heap allocations per read of the `InputPort` read methods, reading
+//! from a `LoopbackBackend` so that no MIDI hardware is needed.
+//!
+//! Run with `cargo bench --bench read_allocations`.
+extern crate portmidi;
+
+use portmidi::backend::LoopbackBackend;
+use portmidi::{InputPort, MidiEvent, MidiMessage, PortMidi};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counts allocations and reallocations, forwarding them to the system allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const BUFFER_SIZE: usize = 1024;
+const READS: usize = 10_000;
+const EVENTS_PER_READ: usize = 16;
+
+/// Writes `EVENTS_PER_READ` events before each of `READS` calls of `read`, which
+/// returns the number of events it read, and prints the allocations and time per read.
+fn measure<F>(name: &str, mut read: F)
+where
+    F: FnMut(&mut InputPort) -> usize,
+{
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend).unwrap();
+    let mut in_port = context
+        .input_port(context.device(input).unwrap(), BUFFER_SIZE)
+        .unwrap();
+    let mut out_port = context
+        .output_port(context.device(output).unwrap(), BUFFER_SIZE)
+        .unwrap();
+    let event = MidiEvent {
+        message: MidiMessage::from([0x90, 60, 100, 0]),
+        timestamp: 0,
+    };
+
+    let mut allocations = 0;
+    let mut elapsed = Duration::from_secs(0);
+    let mut received = 0;
+    for _ in 0..READS {
+        out_port.write_events(vec![event; EVENTS_PER_READ]).unwrap();
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        received += read(&mut in_port);
+        elapsed += start.elapsed();
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+    assert_eq!(received, READS * EVENTS_PER_READ);
+    println!(
+        "{:<10} {:>6.2} allocations/read {:>10?}/read",
+        name,
+        allocations as f64 / READS as f64,
+        elapsed / READS as u32
+    );
+}
+
+fn main() {
+    measure("read_n", |port| {
+        port.read_n(BUFFER_SIZE)
+            .unwrap()
+            .map_or(0, |events| events.len())
+    });
+    let mut events = [MidiEvent::default(); BUFFER_SIZE];
+    measure("read_into", |port| port.read_into(&mut events).unwrap());
+    measure("drain", |port| port.drain().unwrap().count());
+}
@@ -0,0 +1,146 @@
+//! Locates or builds the PortMidi C library.
+//!
+//! * With the `vendored` feature the PortMidi sources in `vendor/portmidi`, or in the
+//!   directory given by `PORTMIDI_SOURCE_DIR`, are compiled and linked statically.
+//!   In a git checkout they must be fetched with `other/update-vendored-portmidi.sh`.
+//! * If `PORTMIDI_LIB_DIR` is set, `libportmidi` is linked from that directory.
+//! * Otherwise `pkg-config` is used to find PortMidi 2.0 or newer, the build fails
+//!   if it isn't found.
+//!
+//! Nothing is linked with the `dynamic` feature, the library is loaded at runtime.
+#[cfg(feature = "vendored")]
+extern crate cc;
+extern crate pkg_config;
+
+use std::env;
+use std::path::PathBuf;
+
+/// Virtual devices were added in PortMidi 2.0.
+const MIN_VERSION: &str = "2.0.0";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=PORTMIDI_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=PORTMIDI_SOURCE_DIR");
+    // docs.rs has no PortMidi and doesn't link
+    if env::var_os("DOCS_RS").is_some() {
+        return;
+    }
+    if cfg!(feature = "dynamic") {
+        if cfg!(feature = "vendored") {
+            fail("the `vendored` and `dynamic` features can't be enabled together");
+        }
+    } else if cfg!(feature = "vendored") {
+        build_vendored();
+    } else if let Some(dir) = env::var_os("PORTMIDI_LIB_DIR") {
+        println!(
+            "cargo:rustc-link-search=native={}",
+            PathBuf::from(dir).display()
+        );
+        println!("cargo:rustc-link-lib=portmidi");
+    } else {
+        probe_pkg_config();
+    }
+}
+
+fn probe_pkg_config() {
+    if let Err(err) = pkg_config::Config::new()
+        .atleast_version(MIN_VERSION)
+        .probe("portmidi")
+    {
+        fail(&format!(
+            "could not find PortMidi {} or newer with pkg-config: {} \
+             Install PortMidi 2.x (e.g. `libportmidi-dev` on Debian/Ubuntu, \
+             `portmidi` on Arch Linux or Homebrew), set PORTMIDI_LIB_DIR to the \
+             directory containing libportmidi, or enable the `vendored` feature \
+             to build PortMidi from source.",
+            MIN_VERSION, err
+        ));
+    }
+}
+
+#[cfg(not(feature = "vendored"))]
+fn build_vendored() {}
+
+#[cfg(feature = "vendored")]
+fn build_vendored() {
+    let root = match env::var_os("PORTMIDI_SOURCE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("vendor/portmidi"),
+    };
+    if !root.join("pm_common/portmidi.c").is_file() {
+        fail(&format!(
+            "the `vendored` feature needs the PortMidi {} sources in {}, they are not \
+             part of the git repository. Run `other/update-vendored-portmidi.sh` once \
+             to fetch them, or set PORTMIDI_SOURCE_DIR to a PortMidi source tree.",
+            MIN_VERSION,
+            root.display()
+        ));
+    }
+    println!("cargo:rerun-if-changed={}", root.display());
+
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let (platform_dir, sources): (&str, &[&str]) = match target_os.as_str() {
+        "linux" => (
+            "pm_linux",
+            &[
+                "pm_linux/pmlinux.c",
+                "pm_linux/pmlinuxalsa.c",
+                "pm_linux/finddefault.c",
+                "porttime/ptlinux.c",
+            ],
+        ),
+        "macos" => (
+            "pm_mac",
+            &[
+                "pm_mac/pmmac.c",
+                "pm_mac/pmmacosxcm.c",
+                "pm_mac/finddefault.c",
+                "porttime/ptmacosx_mach.c",
+            ],
+        ),
+        "windows" => (
+            "pm_win",
+            &["pm_win/pmwin.c", "pm_win/pmwinmm.c", "porttime/ptwindows.c"],
+        ),
+        os => fail(&format!("the `vendored` feature does not support {}", os)),
+    };
+
+    let mut build = cc::Build::new();
+    build
+        .include(root.join("pm_common"))
+        .include(root.join("porttime"))
+        .include(root.join(platform_dir))
+        .file(root.join("pm_common/portmidi.c"))
+        .file(root.join("pm_common/pmutil.c"))
+        .file(root.join("porttime/porttime.c"))
+        .warnings(false);
+    // `finddefault.c` only exists in some releases
+    for source in sources.iter().map(|source| root.join(source)) {
+        if source.is_file() {
+            build.file(source);
+        }
+    }
+    match target_os.as_str() {
+        "linux" => {
+            build.define("PMALSA", None);
+            // ALSA has no vendored build, it must be installed
+            if pkg_config::probe_library("alsa").is_err() {
+                println!("cargo:rustc-link-lib=asound");
+            }
+            println!("cargo:rustc-link-lib=pthread");
+        }
+        "macos" => {
+            for framework in &["CoreAudio", "CoreFoundation", "CoreMIDI", "CoreServices"] {
+                println!("cargo:rustc-link-lib=framework={}", framework);
+            }
+        }
+        _ => println!("cargo:rustc-link-lib=winmm"),
+    }
+    build.compile("portmidi");
+}
+
+/// Stops the build, cargo shows the message with the build script output.
+fn fail(message: &str) -> ! {
+    panic!("{}", message)
+}
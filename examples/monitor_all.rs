@@ -11,21 +11,20 @@ fn main() {
     const BUF_LEN: usize = 1024;
     let (tx, rx) = mpsc::channel();
 
-    thread::spawn(move || {
-        let in_ports = context
-            .devices()
-            .unwrap()
-            .into_iter()
-            .filter_map(|dev| context.input_port(dev, BUF_LEN).ok())
-            .collect::<Vec<_>>();
-        loop {
-            for port in &in_ports {
-                if let Ok(Some(events)) = port.read_n(BUF_LEN) {
-                    tx.send((port.device(), events)).unwrap();
-                }
+    // ports keep the context alive, so they can be moved into the thread
+    let in_ports = context
+        .devices()
+        .unwrap()
+        .into_iter()
+        .filter_map(|dev| context.input_port(dev, BUF_LEN).ok())
+        .collect::<Vec<_>>();
+    thread::spawn(move || loop {
+        for port in &in_ports {
+            if let Ok(Some(events)) = port.read_n(BUF_LEN) {
+                tx.send((port.device(), events)).unwrap();
             }
-            thread::sleep(timeout);
         }
+        thread::sleep(timeout);
     });
 
     loop {
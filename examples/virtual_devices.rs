@@ -1,7 +1,6 @@
 extern crate portmidi as pm;
 
 use pm::MidiMessage;
-use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
@@ -54,17 +53,16 @@ static MELODY: [(u8, u32); 42] = [
 fn main() {
     // initialize the PortMidi context.
     let context = pm::PortMidi::new().unwrap();
-    let context = Arc::new(context);
     let timeout = Duration::from_millis(10);
 
     let v_in = context.create_virtual_input("Virt In 1").unwrap();
     let v_out = context.create_virtual_output("Virt Out 1").unwrap();
 
-    let con2 = Arc::clone(&context);
+    // the port holds a handle to the context, so it can be moved into the thread
+    let out_port = context
+        .output_port(context.device(v_out.id()).unwrap(), 1024)
+        .unwrap();
     thread::spawn(move || {
-        let out_port = con2
-            .output_port(con2.device(v_out.id()).unwrap(), 1024)
-            .unwrap();
         println!("Playing... Connect Virt Out 1 to Virt In 1 to see midi messages on screen...");
         println!("(Note: Windows not supported: midi devices do have to be implemented drivers)");
         println!("Press Crtl-C to abort...");
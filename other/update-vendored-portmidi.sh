@@ -0,0 +1,26 @@
+#!/bin/bash
+
+# Replaces vendor/portmidi with the sources of a PortMidi release, used by the
+# `vendored` feature. Usage: other/update-vendored-portmidi.sh [tag], e.g. v2.0.4
+
+# force errors to quit the script
+set -e
+
+TAG="${1:-v2.0.4}"
+GIT_ROOT=$(git rev-parse --show-toplevel)
+VENDOR_DIR="$GIT_ROOT/vendor/portmidi"
+ARCHIVE_URL="https://github.com/PortMidi/portmidi/archive/refs/tags/$TAG.tar.gz"
+
+echo "fetching: $ARCHIVE_URL"
+echo "vendor dir: $VENDOR_DIR"
+
+rm -rf "$VENDOR_DIR"
+mkdir -p "$VENDOR_DIR"
+curl -sSfL "$ARCHIVE_URL" | tar -xz --strip-components=1 -C "$VENDOR_DIR"
+# only the library sources and the license are needed to build
+find "$VENDOR_DIR" -mindepth 1 -maxdepth 1 \
+    ! -name pm_common ! -name pm_linux ! -name pm_mac ! -name pm_win \
+    ! -name porttime ! -name license.txt -exec rm -rf {} +
+echo "$TAG" > "$VENDOR_DIR/VERSION"
+echo
+echo "Now commit vendor/portmidi"
@@ -0,0 +1,634 @@
+use super::{Backend, DeviceDescription, Stream};
+use device::Direction;
+use ffi;
+use filter::{ChannelMask, InputFilter};
+use io::{InputPortOptions, OutputPortOptions};
+use porttime;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use time::TimeSource;
+use types::*;
+
+const INTERFACE: &str = "Loopback";
+
+struct Device {
+    name: String,
+    direction: Direction,
+    is_virtual: bool,
+    deleted: bool,
+    opened: bool,
+    // the input device that receives the events written to an output device
+    peer: Option<PortMidiDeviceId>,
+    // the events written to an output device
+    sent: Vec<MidiEvent>,
+}
+
+struct Input {
+    key: usize,
+    device: PortMidiDeviceId,
+    buffer_size: usize,
+    queue: VecDeque<MidiEvent>,
+    overflow: bool,
+    filter: InputFilter,
+    channel_mask: ChannelMask,
+    in_sysex: bool,
+    time_source: Option<Arc<dyn TimeSource>>,
+}
+impl Input {
+    /// Returns `true` if the event passes the filter and channel mask, like `Pm_Read` does.
+    fn accepts(&mut self, event: &MidiEvent) -> bool {
+        let status = event.message.status;
+        let bit = match status {
+            0xF8..=0xFF => status & 0x0F,
+            // SysEx data without status byte
+            0x00..=0x7F | 0xF7 if self.in_sysex => 0,
+            0x00..=0x7F | 0xF7 => return false,
+            0xF0 => {
+                self.in_sysex = true;
+                0
+            }
+            0xF1..=0xF6 => {
+                self.in_sysex = false;
+                status & 0x0F
+            }
+            _ => {
+                self.in_sysex = false;
+                if !self.channel_mask.contains(status & 0x0F) {
+                    return false;
+                }
+                0x10 + (status >> 4)
+            }
+        };
+        if bit == 0 && self.in_sysex {
+            let msg = event.message;
+            if [msg.status, msg.data1, msg.data2, msg.data3].contains(&ffi::MIDI_EOX) {
+                self.in_sysex = false;
+            }
+        }
+        self.filter.bits() & 1 << bit == 0
+    }
+
+    fn push(&mut self, mut event: MidiEvent) {
+        if !self.accepts(&event) {
+            return;
+        }
+        if self.queue.len() >= self.buffer_size {
+            self.overflow = true;
+            return;
+        }
+        if let Some(ref time_source) = self.time_source {
+            event.timestamp = time_source.now();
+        }
+        self.queue.push_back(event);
+    }
+}
+
+struct Output {
+    key: usize,
+    device: PortMidiDeviceId,
+    latency: u32,
+    time_source: Option<Arc<dyn TimeSource>>,
+    // the events written with a latency that are not due yet
+    pending: VecDeque<MidiEvent>,
+}
+impl Output {
+    /// Removes the events that are due at `timestamp + latency`, in the order they were
+    /// written, or all events if `all` is set.
+    fn due(&mut self, all: bool) -> Vec<MidiEvent> {
+        let now = match self.time_source {
+            _ if all => ffi::PmTimestamp::MAX,
+            Some(ref time_source) => time_source.now(),
+            None => porttime::time(),
+        };
+        let latency = self.latency;
+        let count = self
+            .pending
+            .iter()
+            .take_while(|event| event.timestamp.saturating_add(latency) <= now)
+            .count();
+        self.pending.drain(..count).collect()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    devices: Vec<Device>,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    next_key: usize,
+    failure: Option<Error>,
+    // the number of events written before the next write fails, see `fail_write_after`
+    write_failure: Option<(usize, Error)>,
+    // the number of contexts using the backend
+    initialized: usize,
+}
+impl State {
+    /// Fails if the backend isn't initialized or a failure was requested with `fail_next`.
+    fn check(&mut self) -> Result<()> {
+        if self.initialized == 0 {
+            return Err(Error::PortMidi(ffi::PmError::PmBadPtr));
+        }
+        match self.failure.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn device(&mut self, id: PortMidiDeviceId) -> Result<&mut Device> {
+        match self.devices.get_mut(id as usize) {
+            Some(device) if id >= 0 && !device.deleted => Ok(device),
+            _ => Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId)),
+        }
+    }
+
+    fn add(&mut self, name: &str, direction: Direction, is_virtual: bool) -> PortMidiDeviceId {
+        self.devices.push(Device {
+            name: name.to_owned(),
+            direction,
+            is_virtual,
+            deleted: false,
+            opened: false,
+            peer: None,
+            sent: Vec::new(),
+        });
+        self.devices.len() as PortMidiDeviceId - 1
+    }
+
+    fn default_device(&self, direction: Direction) -> Option<PortMidiDeviceId> {
+        self.devices
+            .iter()
+            .position(|device| {
+                device.direction == direction && !device.is_virtual && !device.deleted
+            })
+            .map(|id| id as PortMidiDeviceId)
+    }
+
+    /// Records the events written to an output device and forwards them to the peer.
+    fn send_output(&mut self, device: PortMidiDeviceId, events: &[MidiEvent]) {
+        let peer = {
+            let device = &mut self.devices[device as usize];
+            device.sent.extend_from_slice(events);
+            device.peer
+        };
+        if let Some(peer) = peer {
+            self.deliver(peer, events);
+        }
+    }
+
+    /// Sends the events of the output streams that are due.
+    fn flush(&mut self) {
+        let due: Vec<_> = self
+            .outputs
+            .iter_mut()
+            .map(|output| (output.device, output.due(false)))
+            .collect();
+        for (device, events) in due {
+            self.send_output(device, &events);
+        }
+    }
+
+    fn deliver(&mut self, device: PortMidiDeviceId, events: &[MidiEvent]) {
+        for input in self
+            .inputs
+            .iter_mut()
+            .filter(|input| input.device == device)
+        {
+            for &event in events {
+                input.push(event);
+            }
+        }
+    }
+
+    fn open(&mut self, id: PortMidiDeviceId, direction: Direction) -> Result<()> {
+        self.check()?;
+        let device = self.device(id)?;
+        if device.direction != direction || device.opened {
+            return Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId));
+        }
+        device.opened = true;
+        Ok(())
+    }
+}
+
+/// An in-memory `Backend` for tests that don't have access to MIDI hardware.
+///
+/// Devices are added with `add_input`, `add_output` or `add_device_pair`. Events
+/// written to an output device are recorded and can be taken with `sent`, events
+/// written to the output of a device pair are received by its input as well.
+/// Events sent by a simulated device are injected with `send`. Virtual devices
+/// behave like other devices, but are not used as default devices.
+///
+/// Devices added to a backend in use or disconnected with `unplug` are picked up
+/// by `PortMidi::rescan`, like hot plugged devices.
+///
+/// Input streams apply the `InputFilter` and `ChannelMask` and report an
+/// `PmError::PmBufferOverflow` once if their buffer was full, like PortMidi does.
+/// Received events keep their timestamps unless the port has a `TimeSource`.
+/// Events written to a port opened with a latency are held until `timestamp + latency`
+/// of the port's `TimeSource` or PortTime and sent the next time the backend is used.
+/// Closing the port sends the pending events, aborting it discards them.
+///
+/// `LoopbackBackend` is a handle, clones share the same devices.
+///
+/// ```
+/// # use portmidi::backend::LoopbackBackend;
+/// # use portmidi::{MidiMessage, PortMidi};
+/// let backend = LoopbackBackend::new();
+/// let (input, output) = backend.add_device_pair("Loop");
+/// let context = PortMidi::with_backend(backend.clone()).unwrap();
+/// let in_port = context.input_port(context.device(input).unwrap(), 16).unwrap();
+/// let mut out_port = context.output_port(context.device(output).unwrap(), 16).unwrap();
+/// out_port.write_message(MidiMessage::from([0x90, 60, 100, 0])).unwrap();
+/// let events = in_port.read_n(16).unwrap().unwrap();
+/// assert_eq!(events[0].message, MidiMessage::from([0x90, 60, 100, 0]));
+/// assert_eq!(backend.sent(output).len(), 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct LoopbackBackend {
+    state: Arc<Mutex<State>>,
+}
+impl LoopbackBackend {
+    /// Creates a backend without devices.
+    pub fn new() -> Self {
+        LoopbackBackend::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Adds an input device and returns its id.
+    pub fn add_input(&self, name: &str) -> PortMidiDeviceId {
+        self.lock().add(name, Direction::Input, false)
+    }
+
+    /// Adds an output device and returns its id.
+    pub fn add_output(&self, name: &str) -> PortMidiDeviceId {
+        self.lock().add(name, Direction::Output, false)
+    }
+
+    /// Adds an input and an output device with the given name, events written to
+    /// the output are received by the input. Returns the ids of the input and the output.
+    pub fn add_device_pair(&self, name: &str) -> (PortMidiDeviceId, PortMidiDeviceId) {
+        let mut state = self.lock();
+        let input = state.add(name, Direction::Input, false);
+        let output = state.add(name, Direction::Output, false);
+        state.devices[output as usize].peer = Some(input);
+        (input, output)
+    }
+
+    /// Simulates events sent by an input device, they are received by all open
+    /// ports of the device.
+    /// Returns an `Error::PortMidi(PmError::PmInvalidDeviceId)` if the device
+    /// is not an input device.
+    pub fn send(&self, id: PortMidiDeviceId, events: &[MidiEvent]) -> Result<()> {
+        let mut state = self.lock();
+        if state.device(id)?.direction != Direction::Input {
+            return Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId));
+        }
+        state.deliver(id, events);
+        Ok(())
+    }
+
+    /// Returns and clears the events that were written to an output device.
+    /// SysEx messages are split into events of four bytes, like PortMidi delivers them.
+    pub fn sent(&self, id: PortMidiDeviceId) -> Vec<MidiEvent> {
+        let mut state = self.lock();
+        state.flush();
+        match state.devices.get_mut(id as usize) {
+            Some(device) => device.sent.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Simulates disconnecting a device. Like with PortMidi, the device keeps its id
+    /// and is listed until `PortMidi::rescan` is called, but can't be opened anymore.
+    pub fn unplug(&self, id: PortMidiDeviceId) -> Result<()> {
+        self.lock().device(id)?.deleted = true;
+        Ok(())
+    }
+
+    /// Returns `true` while a context uses the backend. Like PortMidi, open ports
+    /// fail with `PmError::PmBadPtr` once the backend is terminated.
+    pub fn is_initialized(&self) -> bool {
+        self.lock().initialized > 0
+    }
+
+    /// Makes the next call that opens a port or uses an open port fail with `err`,
+    /// e.g. a `PmError` or an `Error::Host`. Until then, open ports report a pending
+    /// `Error::Host` with `has_host_error`.
+    pub fn fail_next<E: Into<Error>>(&self, err: E) {
+        self.lock().failure = Some(err.into());
+    }
+
+    /// Makes the output streams write `count` more events and then fail with `err`,
+    /// like `Pm_Write` stops at the event that overflows the buffer. The events of the
+    /// failing write before that event are sent.
+    pub fn fail_write_after<E: Into<Error>>(&self, count: usize, err: E) {
+        self.lock().write_failure = Some((count, err.into()));
+    }
+}
+
+impl Backend for LoopbackBackend {
+    fn initialize(&self) -> Result<()> {
+        self.lock().initialized += 1;
+        Ok(())
+    }
+
+    fn terminate(&self) -> Result<()> {
+        let mut state = self.lock();
+        state.initialized = state.initialized.saturating_sub(1);
+        Ok(())
+    }
+
+    fn count_devices(&self) -> Result<u32> {
+        Ok(self.lock().devices.len() as u32)
+    }
+
+    fn default_input_device_id(&self) -> Option<PortMidiDeviceId> {
+        self.lock().default_device(Direction::Input)
+    }
+
+    fn default_output_device_id(&self) -> Option<PortMidiDeviceId> {
+        self.lock().default_device(Direction::Output)
+    }
+
+    fn device_info(&self, id: PortMidiDeviceId) -> Option<DeviceDescription> {
+        if id < 0 {
+            return None;
+        }
+        self.lock()
+            .devices
+            .get(id as usize)
+            .map(|device| DeviceDescription {
+                interface: INTERFACE.to_owned(),
+                name: device.name.clone(),
+                input: device.direction == Direction::Input,
+                output: device.direction == Direction::Output,
+                opened: device.opened,
+                is_virtual: device.is_virtual,
+            })
+    }
+
+    fn rescan(&self) -> Result<()> {
+        let mut state = self.lock();
+        // removes unplugged and deleted devices, the ids of the others shift down
+        let mut ids = Vec::with_capacity(state.devices.len());
+        let mut next = 0;
+        for device in &state.devices {
+            ids.push(if device.deleted { None } else { Some(next) });
+            if !device.deleted {
+                next += 1;
+            }
+        }
+        state.devices.retain(|device| !device.deleted);
+        for device in &mut state.devices {
+            device.peer = device.peer.and_then(|peer| ids[peer as usize]);
+        }
+        Ok(())
+    }
+
+    fn create_virtual_device(
+        &self,
+        name: &str,
+        interface: Option<&str>,
+        direction: Direction,
+    ) -> Result<PortMidiDeviceId> {
+        let mut state = self.lock();
+        if let Some(interface) = interface.filter(|&interface| interface != INTERFACE) {
+            return Err(Error::InvalidName(interface.to_owned()));
+        }
+        if name.is_empty() || name.contains('\0') {
+            return Err(Error::InvalidName(name.to_owned()));
+        }
+        let exists = state
+            .devices
+            .iter()
+            .any(|device| !device.deleted && device.direction == direction && device.name == name);
+        if exists {
+            return Err(Error::DuplicateVirtualName(name.to_owned()));
+        }
+        Ok(state.add(name, direction, true))
+    }
+
+    fn delete_virtual_device(&self, id: PortMidiDeviceId) -> Result<()> {
+        let mut state = self.lock();
+        let device = state.device(id)?;
+        if !device.is_virtual {
+            Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId))
+        } else if device.opened {
+            Err(Error::PortMidi(ffi::PmError::PmBadPtr))
+        } else {
+            device.deleted = true;
+            Ok(())
+        }
+    }
+
+    fn open_input(
+        &self,
+        id: PortMidiDeviceId,
+        options: &InputPortOptions,
+    ) -> Result<Box<dyn Stream>> {
+        let mut state = self.lock();
+        state.open(id, Direction::Input)?;
+        let key = state.next_key;
+        state.next_key += 1;
+        state.inputs.push(Input {
+            key,
+            device: id,
+            buffer_size: options.buffer_size(),
+            queue: VecDeque::new(),
+            overflow: false,
+            filter: InputFilter::empty(),
+            channel_mask: ChannelMask::ALL,
+            in_sysex: false,
+            time_source: options.time_source().cloned(),
+        });
+        Ok(Box::new(LoopbackStream {
+            state: self.state.clone(),
+            device: id,
+            input: Some(key),
+            output: None,
+        }))
+    }
+
+    fn open_output(
+        &self,
+        id: PortMidiDeviceId,
+        options: &OutputPortOptions,
+    ) -> Result<Box<dyn Stream>> {
+        let mut state = self.lock();
+        state.open(id, Direction::Output)?;
+        let key = state.next_key;
+        state.next_key += 1;
+        state.outputs.push(Output {
+            key,
+            device: id,
+            latency: options.latency(),
+            time_source: options.time_source().cloned(),
+            pending: VecDeque::new(),
+        });
+        Ok(Box::new(LoopbackStream {
+            state: self.state.clone(),
+            device: id,
+            input: None,
+            output: Some(key),
+        }))
+    }
+}
+
+/// A stream opened by the `LoopbackBackend`.
+struct LoopbackStream {
+    state: Arc<Mutex<State>>,
+    device: PortMidiDeviceId,
+    // the key of the `Input` of an input stream
+    input: Option<usize>,
+    // the key of the `Output` of an output stream
+    output: Option<usize>,
+}
+impl LoopbackStream {
+    /// Locks the state and returns the `Input` of an input stream.
+    fn with_input<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Input) -> Result<T>,
+    {
+        let mut state = self.state.lock().unwrap();
+        state.check()?;
+        state.flush();
+        let key = self.input;
+        match state.inputs.iter_mut().find(|input| Some(input.key) == key) {
+            Some(input) => f(input),
+            None => Err(Error::PortMidi(ffi::PmError::PmBadPtr)),
+        }
+    }
+
+    /// Sends the events written to an output stream, or queues them if the stream
+    /// has a latency.
+    fn output(&self, events: &[MidiEvent]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.check()?;
+        state.flush();
+        let key = self.output;
+        let index = match state
+            .outputs
+            .iter()
+            .position(|output| Some(output.key) == key)
+        {
+            Some(index) => index,
+            None => return Err(Error::PortMidi(ffi::PmError::PmBadPtr)),
+        };
+        let (events, result) = match state.write_failure.take() {
+            Some((count, err)) if count < events.len() => (&events[..count], Err(err)),
+            Some((count, err)) => {
+                state.write_failure = Some((count - events.len(), err));
+                (events, Ok(()))
+            }
+            None => (events, Ok(())),
+        };
+        if state.outputs[index].latency > 0 {
+            state.outputs[index].pending.extend(events);
+            state.flush();
+        } else {
+            state.send_output(self.device, events);
+        }
+        result
+    }
+}
+impl Stream for LoopbackStream {
+    fn read(&self, output: &mut [MidiEvent]) -> Result<usize> {
+        self.with_input(|input| {
+            if input.overflow {
+                input.overflow = false;
+                return Err(Error::PortMidi(ffi::PmError::PmBufferOverflow));
+            }
+            let count = output.len().min(input.queue.len());
+            for (slot, event) in output.iter_mut().zip(input.queue.drain(..count)) {
+                *slot = event;
+            }
+            Ok(count)
+        })
+    }
+
+    fn poll(&self) -> Result<bool> {
+        self.with_input(|input| Ok(!input.queue.is_empty()))
+    }
+
+    fn write(&self, events: &[MidiEvent]) -> Result<()> {
+        self.output(events)
+    }
+
+    fn write_short(&self, timestamp: ffi::PmTimestamp, message: MidiMessage) -> Result<()> {
+        self.output(&[MidiEvent { message, timestamp }])
+    }
+
+    fn write_sysex(&self, timestamp: ffi::PmTimestamp, data: &[u8]) -> Result<()> {
+        if data.first() != Some(&0xF0) || data.last() != Some(&ffi::MIDI_EOX) {
+            return Err(Error::PortMidi(ffi::PmError::PmBadData));
+        }
+        let events: Vec<MidiEvent> = data
+            .chunks(4)
+            .map(|chunk| {
+                let mut raw = [0; 4];
+                raw[..chunk.len()].copy_from_slice(chunk);
+                MidiEvent {
+                    message: MidiMessage::from(raw),
+                    timestamp,
+                }
+            })
+            .collect();
+        self.output(&events)
+    }
+
+    fn set_filter(&self, filter: InputFilter) -> Result<()> {
+        self.with_input(|input| {
+            input.filter = filter;
+            Ok(())
+        })
+    }
+
+    fn set_channel_mask(&self, mask: ChannelMask) -> Result<()> {
+        self.with_input(|input| {
+            input.channel_mask = mask;
+            Ok(())
+        })
+    }
+
+    fn has_host_error(&self) -> bool {
+        matches!(self.state.lock().unwrap().failure, Some(Error::Host { .. }))
+    }
+
+    fn abort(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.check()?;
+        let key = self.output;
+        match state
+            .outputs
+            .iter_mut()
+            .find(|output| Some(output.key) == key)
+        {
+            Some(output) => {
+                output.pending.clear();
+                Ok(())
+            }
+            None => Err(Error::PortMidi(ffi::PmError::PmBadPtr)),
+        }
+    }
+
+    fn close(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let key = self.input;
+        state.inputs.retain(|input| Some(input.key) != key);
+        let key = self.output;
+        if let Some(index) = state
+            .outputs
+            .iter()
+            .position(|output| Some(output.key) == key)
+        {
+            let mut output = state.outputs.remove(index);
+            let events = output.due(true);
+            state.send_output(self.device, &events);
+        }
+        state.devices[self.device as usize].opened = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! The interface between the safe API and the MIDI implementation.
+//!
+//! `PortMidi`, the ports and virtual devices do all their work through a `Backend`.
+//! `NativeBackend` calls the PortMidi C library and is used by `PortMidi::new`,
+//! `LoopbackBackend` is an in-memory implementation that doesn't need any MIDI
+//! hardware, use it with `PortMidi::with_backend` to test code deterministically.
+use device::Direction;
+use ffi::PmTimestamp;
+use filter::{ChannelMask, InputFilter};
+use io::{InputPortOptions, OutputPortOptions};
+use types::*;
+
+mod loopback;
+mod native;
+pub use self::loopback::LoopbackBackend;
+pub use self::native::NativeBackend;
+
+/// The information a `Backend` provides about a device, see `PmDeviceInfo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceDescription {
+    /// The underlying MIDI API, e.g. `ALSA` or `CoreMIDI`.
+    pub interface: String,
+    pub name: String,
+    pub input: bool,
+    pub output: bool,
+    /// `true` while a port of the device is open.
+    pub opened: bool,
+    /// `true` for a device created with `create_virtual_device`.
+    pub is_virtual: bool,
+}
+
+/// A MIDI implementation, the methods mirror the device related functions of PortMidi.
+pub trait Backend: Send + Sync {
+    /// Initializes the implementation, called by `PortMidi` on creation.
+    ///
+    /// Several contexts may share an implementation, so calls can be nested and only
+    /// the `terminate` matching the first `initialize` may release it.
+    fn initialize(&self) -> Result<()>;
+
+    /// Releases the implementation, called by `PortMidi` when the last handle is dropped.
+    fn terminate(&self) -> Result<()>;
+
+    /// Returns the number of devices, device ids range from `0` to `count_devices() - 1`.
+    fn count_devices(&self) -> Result<u32>;
+
+    /// Returns the id of the default input device, if any.
+    fn default_input_device_id(&self) -> Option<PortMidiDeviceId>;
+
+    /// Returns the id of the default output device, if any.
+    fn default_output_device_id(&self) -> Option<PortMidiDeviceId>;
+
+    /// Returns the description of the device or `None` if the id is invalid.
+    fn device_info(&self, id: PortMidiDeviceId) -> Option<DeviceDescription>;
+
+    /// Refreshes the device list, so that devices connected or disconnected since
+    /// `initialize` are picked up. Device ids may change.
+    ///
+    /// Only called when no port is open and no virtual device exists, the default
+    /// implementation terminates and initializes the backend again.
+    fn rescan(&self) -> Result<()> {
+        self.terminate()?;
+        self.initialize()
+    }
+
+    /// Creates a virtual device of the MIDI API `interface`, or the default one if it is
+    /// `None`, and returns its id.
+    ///
+    /// Returns an `Error::DuplicateVirtualName` if the name is already in use and an
+    /// `Error::InvalidName` if the interface is not supported.
+    fn create_virtual_device(
+        &self,
+        name: &str,
+        interface: Option<&str>,
+        direction: Direction,
+    ) -> Result<PortMidiDeviceId>;
+
+    /// Deletes a virtual device created with `create_virtual_device`.
+    fn delete_virtual_device(&self, id: PortMidiDeviceId) -> Result<()>;
+
+    /// Opens an input stream for the device.
+    fn open_input(
+        &self,
+        id: PortMidiDeviceId,
+        options: &InputPortOptions,
+    ) -> Result<Box<dyn Stream>>;
+
+    /// Opens an output stream for the device.
+    fn open_output(
+        &self,
+        id: PortMidiDeviceId,
+        options: &OutputPortOptions,
+    ) -> Result<Box<dyn Stream>>;
+}
+
+/// An open input or output stream, the methods mirror the stream related functions
+/// of PortMidi.
+pub trait Stream: Send {
+    /// Reads at most `output.len()` received events into `output` and returns their number.
+    fn read(&self, output: &mut [MidiEvent]) -> Result<usize>;
+
+    /// Returns `true` if events are available.
+    fn poll(&self) -> Result<bool>;
+
+    /// Writes the events, the timestamps are honored if the stream has a latency.
+    fn write(&self, events: &[MidiEvent]) -> Result<()>;
+
+    /// Writes a single message.
+    fn write_short(&self, timestamp: PmTimestamp, message: MidiMessage) -> Result<()>;
+
+    /// Writes an EOX-terminated SysEx message.
+    fn write_sysex(&self, timestamp: PmTimestamp, data: &[u8]) -> Result<()>;
+
+    /// Sets the message types that are dropped by an input stream.
+    fn set_filter(&self, filter: InputFilter) -> Result<()>;
+
+    /// Sets the channels whose messages are passed by an input stream.
+    fn set_channel_mask(&self, mask: ChannelMask) -> Result<()>;
+
+    /// Returns `true` if the host MIDI API reported an error that hasn't been returned
+    /// by a call yet.
+    fn has_host_error(&self) -> bool;
+
+    /// Discards the events of an output stream that are not sent yet, only `close`
+    /// is called afterwards.
+    fn abort(&self) -> Result<()>;
+
+    /// Closes the stream, no other method is called afterwards.
+    fn close(&self) -> Result<()>;
+}
@@ -0,0 +1,423 @@
+use super::{Backend, DeviceDescription, Stream};
+use device::Direction;
+use ffi;
+use ffi::MaybeError;
+use filter::{ChannelMask, InputFilter};
+use io::{InputPortOptions, OutputPortOptions};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::{Mutex, PoisonError};
+use time::TimeProc;
+use types::*;
+use PM_HOST_ERROR_MSG_LEN;
+
+/// The `Backend` that calls the PortMidi C library.
+///
+/// PortMidi is global to the process, so initialization is reference counted:
+/// `Pm_Initialize` is only called by the first `initialize` and `Pm_Terminate`
+/// by the last `terminate`, contexts don't terminate PortMidi under each other.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeBackend;
+
+/// The number of `initialize` calls without a matching `terminate`.
+static INITIALIZED: Mutex<usize> = Mutex::new(0);
+
+/// Terminates and initializes PortMidi again. If initializing fails PortMidi is left
+/// terminated, so the count is reset and the next `initialize` calls `Pm_Initialize`.
+fn reinitialize<T, I>(initialized: &mut usize, terminate: T, initialize: I) -> Result<()>
+where
+    T: FnOnce() -> ffi::PmError,
+    I: FnOnce() -> ffi::PmError,
+{
+    Result::from(terminate())?;
+    Result::from(initialize()).map_err(|err| {
+        *initialized = 0;
+        err
+    })
+}
+
+/// Converts the result of `Pm_CreateVirtualInput/Output`, any negative value is an error.
+fn virtual_device_id(id: c_int, name: &str, interface: Option<&str>) -> Result<PortMidiDeviceId> {
+    match id {
+        id if id >= 0 => Ok(id),
+        ffi::PM_NAME_CONFLICT => Err(Error::DuplicateVirtualName(name.to_owned())),
+        ffi::PM_INTERFACE_NOT_SUPPORTED => {
+            Err(Error::InvalidName(interface.unwrap_or_default().to_owned()))
+        }
+        err => match ffi::PmError::try_from(err) {
+            Err(err) => Err(Error::PortMidi(err)),
+            // not a PortMidi error code
+            Ok(_) => Err(Error::PortMidi(ffi::PmError::PmInternalError)),
+        },
+    }
+}
+
+/// Returns the `time_proc` and `time_info` arguments for opening a stream.
+fn time_args(time_proc: &Option<TimeProc>) -> (Option<ffi::PmTimeProcPtr>, *const c_void) {
+    match *time_proc {
+        Some(ref time_proc) => (time_proc.time_proc(), time_proc.time_info()),
+        None => (None, ptr::null()),
+    }
+}
+
+impl Backend for NativeBackend {
+    fn initialize(&self) -> Result<()> {
+        #[cfg(feature = "dynamic")]
+        ::library::load()?;
+        let mut initialized = INITIALIZED.lock().unwrap_or_else(PoisonError::into_inner);
+        if *initialized == 0 {
+            Result::from(unsafe { ffi::Pm_Initialize() })?;
+        }
+        *initialized += 1;
+        Ok(())
+    }
+
+    fn terminate(&self) -> Result<()> {
+        let mut initialized = INITIALIZED.lock().unwrap_or_else(PoisonError::into_inner);
+        match *initialized {
+            0 => Ok(()),
+            1 => {
+                *initialized = 0;
+                Result::from(unsafe { ffi::Pm_Terminate() })
+            }
+            _ => {
+                *initialized -= 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn rescan(&self) -> Result<()> {
+        // reinitializing would invalidate the devices of the other contexts
+        let mut initialized = INITIALIZED.lock().unwrap_or_else(PoisonError::into_inner);
+        if *initialized > 1 {
+            return Err(Error::DevicesInUse);
+        }
+        reinitialize(
+            &mut initialized,
+            || unsafe { ffi::Pm_Terminate() },
+            || unsafe { ffi::Pm_Initialize() },
+        )
+    }
+
+    fn count_devices(&self) -> Result<u32> {
+        match unsafe { ffi::Pm_CountDevices() } {
+            count if count >= 0 => Ok(count as u32),
+            _ => Err(Error::Invalid),
+        }
+    }
+
+    fn default_input_device_id(&self) -> Option<PortMidiDeviceId> {
+        match unsafe { ffi::Pm_GetDefaultInputDeviceID() } {
+            ffi::PM_NO_DEVICE => None,
+            id => Some(id),
+        }
+    }
+
+    fn default_output_device_id(&self) -> Option<PortMidiDeviceId> {
+        match unsafe { ffi::Pm_GetDefaultOutputDeviceID() } {
+            ffi::PM_NO_DEVICE => None,
+            id => Some(id),
+        }
+    }
+
+    fn device_info(&self, id: PortMidiDeviceId) -> Option<DeviceDescription> {
+        let dev_inf_ptr = unsafe { ffi::Pm_GetDeviceInfo(id) };
+        if dev_inf_ptr.is_null() {
+            return None;
+        }
+        let info = unsafe { &*dev_inf_ptr };
+        Some(DeviceDescription {
+            interface: ffi::ptr_to_string(info.interf).unwrap_or_default(),
+            name: ffi::ptr_to_string(info.name).unwrap(),
+            input: info.input != 0,
+            output: info.output != 0,
+            opened: info.opened != 0,
+            is_virtual: info.is_virtual != 0,
+        })
+    }
+
+    fn create_virtual_device(
+        &self,
+        name: &str,
+        interface: Option<&str>,
+        direction: Direction,
+    ) -> Result<PortMidiDeviceId> {
+        let c_name = CString::new(name).map_err(|_| Error::InvalidName(name.to_owned()))?;
+        let c_interface = match interface {
+            Some(interface) => Some(
+                CString::new(interface).map_err(|_| Error::InvalidName(interface.to_owned()))?,
+            ),
+            None => None,
+        };
+        let interface_ptr = c_interface.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        let id = match direction {
+            Direction::Input => unsafe {
+                ffi::Pm_CreateVirtualInput(c_name.as_ptr(), interface_ptr, ptr::null())
+            },
+            Direction::Output => unsafe {
+                ffi::Pm_CreateVirtualOutput(c_name.as_ptr(), interface_ptr, ptr::null())
+            },
+        };
+        match virtual_device_id(id as c_int, name, interface) {
+            Err(Error::PortMidi(ffi::PmError::PmHostError)) => Err(host_error(ptr::null())),
+            result => result,
+        }
+    }
+
+    fn delete_virtual_device(&self, id: PortMidiDeviceId) -> Result<()> {
+        check(ptr::null(), unsafe { ffi::Pm_DeleteVirtualDevice(id) })
+    }
+
+    fn open_input(
+        &self,
+        id: PortMidiDeviceId,
+        options: &InputPortOptions,
+    ) -> Result<Box<dyn Stream>> {
+        let time_proc = options.time_source().cloned().map(TimeProc::new);
+        let (time_proc_ptr, time_info) = time_args(&time_proc);
+        let raw_stream: *const ffi::PortMidiStream = ptr::null();
+        check(raw_stream, unsafe {
+            ffi::Pm_OpenInput(
+                &raw_stream as *const *const _,
+                id,
+                ptr::null(), // *inputDriverInfo, not needed for normal operation
+                options.buffer_size() as c_int,
+                time_proc_ptr, // PmTimeProcPtr, a procedure that returns time in ms
+                time_info,
+            ) // time_info, a pointer passed to the time procedure
+        })?;
+        Ok(Box::new(NativeStream {
+            stream: raw_stream,
+            events: Mutex::new(Vec::with_capacity(options.buffer_size())),
+            _time_proc: time_proc,
+        }))
+    }
+
+    fn open_output(
+        &self,
+        id: PortMidiDeviceId,
+        options: &OutputPortOptions,
+    ) -> Result<Box<dyn Stream>> {
+        let time_proc = options.time_source().cloned().map(TimeProc::new);
+        let (time_proc_ptr, time_info) = time_args(&time_proc);
+        let raw_stream: *const ffi::PortMidiStream = ptr::null();
+        check(raw_stream, unsafe {
+            ffi::Pm_OpenOutput(
+                &raw_stream as *const *const _,
+                id,
+                ptr::null(), // *inputDriverInfo, not needed for normal operation
+                options.buffer_size() as c_int,
+                time_proc_ptr, // PmTimeProcPtr, a procedure that returns time in ms,
+                time_info,     // time_info, a pointer passed to the time procedure
+                options.latency() as i32, // latency, clamped to i32::MAX by the options
+            )
+        })?;
+        Ok(Box::new(NativeStream {
+            stream: raw_stream,
+            events: Mutex::new(Vec::with_capacity(options.buffer_size())),
+            _time_proc: time_proc,
+        }))
+    }
+}
+
+/// Converts the result of a PortMidi call, a `PmHostError` is returned as an
+/// `Error::Host` with the pending host error of `stream`, which may be null.
+fn check(stream: *const ffi::PortMidiStream, err: ffi::PmError) -> Result<()> {
+    match err {
+        ffi::PmError::PmHostError => Err(host_error(stream)),
+        err => Result::from(err),
+    }
+}
+
+/// Takes the pending host error, PortMidi only keeps the last one.
+fn host_error(stream: *const ffi::PortMidiStream) -> Error {
+    // makes PortMidi copy the host error of the stream into the error text
+    unsafe { ffi::Pm_HasHostError(stream) };
+    let mut text: [c_char; PM_HOST_ERROR_MSG_LEN as usize] = [0; PM_HOST_ERROR_MSG_LEN as usize];
+    unsafe { ffi::Pm_GetHostErrorText(text.as_mut_ptr(), PM_HOST_ERROR_MSG_LEN) };
+    Error::Host {
+        // PortMidi doesn't expose the host's error number
+        code: None,
+        message: ffi::ptr_to_string(text.as_ptr()).unwrap_or_default(),
+    }
+}
+
+/// Converts the events into `buffer`, which is passed to `Pm_Write`.
+fn to_pm_events(events: &[MidiEvent], buffer: &mut Vec<ffi::PmEvent>) {
+    buffer.clear();
+    buffer.extend(events.iter().map(|&event| -> ffi::PmEvent { event.into() }));
+}
+
+/// A stream opened by the `NativeBackend`.
+struct NativeStream {
+    stream: *const ffi::PortMidiStream,
+    // the buffer passed to `Pm_Read` or `Pm_Write`, reused so that they don't allocate
+    events: Mutex<Vec<ffi::PmEvent>>,
+    // Must outlive the stream, PortMidi calls it until the stream is closed
+    _time_proc: Option<TimeProc>,
+}
+impl Stream for NativeStream {
+    fn read(&self, output: &mut [MidiEvent]) -> Result<usize> {
+        let mut event_buffer = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        event_buffer.resize(output.len(), ffi::PmEvent::default());
+        let res = unsafe {
+            ffi::Pm_Read(
+                self.stream,
+                event_buffer.as_mut_ptr(),
+                output.len() as c_int,
+            )
+        };
+        match ffi::PmError::try_from(res) {
+            Ok(event_cnt) => {
+                let events = &event_buffer[..event_cnt as usize];
+                for (slot, &event) in output.iter_mut().zip(events) {
+                    *slot = MidiEvent::from(event);
+                }
+                Ok(event_cnt as usize)
+            }
+            Err(ffi::PmError::PmNoError) => Ok(0),
+            Err(err) => check(self.stream, err).map(|_| 0),
+        }
+    }
+
+    fn poll(&self) -> Result<bool> {
+        match unsafe { ffi::Pm_Poll(self.stream) } {
+            ffi::PmError::PmNoError => Ok(false),
+            ffi::PmError::PmGotData => Ok(true),
+            err => check(self.stream, err).map(|_| false),
+        }
+    }
+
+    fn write(&self, events: &[MidiEvent]) -> Result<()> {
+        let mut event_buffer = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        to_pm_events(events, &mut event_buffer);
+        let events = &*event_buffer;
+        check(self.stream, unsafe {
+            ffi::Pm_Write(self.stream, events.as_ptr(), events.len() as c_int)
+        })
+    }
+
+    fn write_short(&self, timestamp: ffi::PmTimestamp, message: MidiMessage) -> Result<()> {
+        check(self.stream, unsafe {
+            ffi::Pm_WriteShort(self.stream, timestamp, message.into())
+        })
+    }
+
+    fn write_sysex(&self, timestamp: ffi::PmTimestamp, data: &[u8]) -> Result<()> {
+        check(self.stream, unsafe {
+            ffi::Pm_WriteSysEx(self.stream, timestamp, data.as_ptr())
+        })
+    }
+
+    fn set_filter(&self, filter: InputFilter) -> Result<()> {
+        check(self.stream, unsafe {
+            ffi::Pm_SetFilter(self.stream, filter.bits())
+        })
+    }
+
+    fn set_channel_mask(&self, mask: ChannelMask) -> Result<()> {
+        check(self.stream, unsafe {
+            ffi::Pm_SetChannelMask(self.stream, mask.bits() as c_int)
+        })
+    }
+
+    fn has_host_error(&self) -> bool {
+        unsafe { ffi::Pm_HasHostError(self.stream) != 0 }
+    }
+
+    fn abort(&self) -> Result<()> {
+        check(self.stream, unsafe { ffi::Pm_Abort(self.stream) })
+    }
+
+    fn close(&self) -> Result<()> {
+        check(self.stream, unsafe { ffi::Pm_Close(self.stream) })
+    }
+}
+unsafe impl Send for NativeStream {}
+
+#[cfg(test)]
+mod tests {
+    use super::{reinitialize, to_pm_events, virtual_device_id};
+    use ffi::PmError;
+    use types::{Error, MidiEvent, MidiMessage};
+
+    #[test]
+    fn timestamps_are_forwarded_unchanged() {
+        let message = MidiMessage::from([0x90, 60, 100, 0]);
+        let events = vec![
+            MidiEvent {
+                message,
+                timestamp: 0,
+            },
+            MidiEvent {
+                message,
+                timestamp: 1500,
+            },
+            MidiEvent {
+                message,
+                timestamp: u32::MAX,
+            },
+        ];
+        let mut pm_events = Vec::new();
+        to_pm_events(&events, &mut pm_events);
+        assert_eq!(pm_events.len(), events.len());
+        for (pm_event, event) in pm_events.iter().zip(events.iter()) {
+            assert_eq!(pm_event.timestamp, event.timestamp);
+            assert_eq!(MidiMessage::from(pm_event.message), event.message);
+        }
+    }
+
+    #[test]
+    fn failed_reinitialization_resets_the_count() {
+        let mut initialized = 1;
+        let result = reinitialize(
+            &mut initialized,
+            || PmError::PmNoError,
+            || PmError::PmNoError,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(initialized, 1);
+
+        let result = reinitialize(
+            &mut initialized,
+            || PmError::PmNoError,
+            || PmError::PmHostError,
+        );
+        assert_eq!(result, Err(Error::PortMidi(PmError::PmHostError)));
+        assert_eq!(initialized, 0);
+
+        // PortMidi is still initialized if terminating fails
+        let mut initialized = 1;
+        let result = reinitialize(
+            &mut initialized,
+            || PmError::PmHostError,
+            || panic!("initialized again"),
+        );
+        assert!(result.is_err());
+        assert_eq!(initialized, 1);
+    }
+
+    #[test]
+    fn negative_virtual_device_ids_are_errors() {
+        assert_eq!(virtual_device_id(0, "Virt", None), Ok(0));
+        assert_eq!(virtual_device_id(3, "Virt", None), Ok(3));
+        assert_eq!(
+            virtual_device_id(-9989, "Virt", None),
+            Err(Error::DuplicateVirtualName("Virt".to_owned()))
+        );
+        assert_eq!(
+            virtual_device_id(-9990, "Virt", Some("JACK")),
+            Err(Error::InvalidName("JACK".to_owned()))
+        );
+        assert_eq!(
+            virtual_device_id(-9991, "Virt", None),
+            Err(Error::PortMidi(PmError::PmNotImplemented))
+        );
+        assert_eq!(
+            virtual_device_id(-1, "Virt", None),
+            Err(Error::PortMidi(PmError::PmInternalError))
+        );
+    }
+}
@@ -1,61 +1,163 @@
+use backend::{Backend, NativeBackend, Stream};
 use device::{DeviceInfo, Direction};
+use duplex::{self, DevicePair, DuplexPort};
 use ffi;
-use io::{InputPort, OutputPort};
+use io::{InputPort, InputPortOptions, OutputPort, OutputPortOptions};
+use query::DeviceQuery;
 use std::os::raw::c_int;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
 use types::{Error, PortMidiDeviceId, Result};
-use vdevice::VirtualDevice;
+use vdevice::{VirtualDevice, VirtualDeviceOptions};
 
 /// The PortMidi base struct.
-/// Initializes PortMidi on creation and terminates it on drop.
+/// Initializes PortMidi on creation and terminates it when the last handle is dropped.
+///
+/// `PortMidi` is a reference-counted handle, clones share the same context. Ports and
+/// virtual devices hold a handle as well, so they can outlive the `PortMidi` they were
+/// created with, e.g. in a `'static` thread.
+#[derive(Clone)]
 pub struct PortMidi {
-    device_count: u32,
+    inner: Arc<Context>,
+}
+
+/// The context of `PortMidi::shared` while a handle to it exists.
+static SHARED: Mutex<Option<Weak<Context>>> = Mutex::new(None);
+
+/// The largest suffix appended to a name by `VirtualDeviceOptions::with_unique_name`.
+const MAX_UNIQUE_NAME_SUFFIX: usize = 100;
+
+struct Context {
+    // also serializes `rescan` with opening ports and creating virtual devices
+    devices: Mutex<Devices>,
+    backend: Arc<dyn Backend>,
+}
+
+struct Devices {
+    count: u32,
+    // the virtual devices created with the context, deleted when it is dropped
+    virtual_devices: Vec<DeviceInfo>,
 }
 
 impl PortMidi {
     /// Initializes the underlying PortMidi C library.
-    /// PortMidi does not support *hot plugging*, this means
-    /// that devices that are connect after calling `new`
-    /// are not picked up.
+    /// PortMidi does not support *hot plugging*, devices that are connected
+    /// after calling `new` are only picked up by `rescan`.
     pub fn new() -> Result<Self> {
-        Result::from(unsafe { ffi::Pm_Initialize() })?;
-        let device_count = unsafe { ffi::Pm_CountDevices() };
-        if device_count >= 0 {
-            Ok(PortMidi {
-                device_count: device_count as u32,
-            })
-        } else {
-            Err(Error::Invalid)
+        PortMidi::with_backend(NativeBackend)
+    }
+
+    /// Returns a handle to the process-wide context, it is created on the first call
+    /// and kept while a handle, port or virtual device of it exists.
+    ///
+    /// Use this in libraries, so that they share one context with the application.
+    /// Contexts created with `new` work alongside it, PortMidi is only terminated
+    /// when the last context is dropped.
+    pub fn shared() -> Result<Self> {
+        let mut shared = SHARED.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(inner) = shared.as_ref().and_then(Weak::upgrade) {
+            return Ok(PortMidi { inner });
         }
+        let context = PortMidi::new()?;
+        *shared = Some(Arc::downgrade(&context.inner));
+        Ok(context)
     }
 
-    /// Return the number of devices. This number will not change during the lifetime
-    /// of the program.
+    /// Initializes the given `Backend` and uses it for all devices and ports,
+    /// e.g. a `LoopbackBackend` to run tests without MIDI hardware.
+    pub fn with_backend<B: Backend + 'static>(backend: B) -> Result<Self> {
+        backend.initialize()?;
+        let device_count = backend.count_devices()?;
+        Ok(PortMidi {
+            inner: Arc::new(Context {
+                devices: Mutex::new(Devices {
+                    count: device_count,
+                    virtual_devices: Vec::new(),
+                }),
+                backend: Arc::new(backend),
+            }),
+        })
+    }
+
+    /// Locks the device state, a panic while it was locked leaves it consistent.
+    fn lock_devices(&self) -> MutexGuard<'_, Devices> {
+        self.inner
+            .devices
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Return the number of devices. This number only changes when calling `rescan`.
     pub fn device_count(&self) -> PortMidiDeviceId {
-        self.device_count as c_int
+        self.lock_devices().count as c_int
+    }
+
+    /// Refreshes the device list, so that devices connected or disconnected since
+    /// `new` are picked up. PortMidi is initialized again, so this only works while
+    /// no port is open, no virtual device exists and no other context uses PortMidi,
+    /// otherwise `Error::DevicesInUse` is returned.
+    ///
+    /// The ids of the devices may change, `DeviceInfo`s obtained before must not be
+    /// used to open ports. Use a `DeviceWatcher` to get notified about changes.
+    pub fn rescan(&self) -> Result<()> {
+        let mut devices = self.lock_devices();
+        for id in 0..self.inner.backend.count_devices()? as PortMidiDeviceId {
+            match self.inner.backend.device_info(id) {
+                Some(ref description) if description.opened || description.is_virtual => {
+                    return Err(Error::DevicesInUse)
+                }
+                _ => (),
+            }
+        }
+        self.inner.backend.rescan()?;
+        devices.count = self.inner.backend.count_devices()?;
+        Ok(())
+    }
+
+    /// Opens an input stream, not while the devices are rescanned.
+    pub(crate) fn open_input(
+        &self,
+        id: PortMidiDeviceId,
+        options: &InputPortOptions,
+    ) -> Result<Box<dyn Stream>> {
+        let _devices = self.lock_devices();
+        self.inner.backend.open_input(id, options)
+    }
+
+    /// Opens an output stream, not while the devices are rescanned.
+    pub(crate) fn open_output(
+        &self,
+        id: PortMidiDeviceId,
+        options: &OutputPortOptions,
+    ) -> Result<Box<dyn Stream>> {
+        let _devices = self.lock_devices();
+        self.inner.backend.open_output(id, options)
     }
 
     /// Returns the `PortMidiDeviceId` for the default input device, or an `Error::NoDefaultDevice` if
     /// there is no available.
     pub fn default_input_device_id(&self) -> Result<PortMidiDeviceId> {
-        match unsafe { ffi::Pm_GetDefaultInputDeviceID() } {
-            ffi::PM_NO_DEVICE => Err(Error::NoDefaultDevice),
-            id => Ok(id),
-        }
+        self.inner
+            .backend
+            .default_input_device_id()
+            .ok_or(Error::NoDefaultDevice)
     }
 
     /// Returns the `PortMidiDeviceId` for the default output device, or an `Error::NoDefaultDevice` if
     /// there is no available.
     pub fn default_output_device_id(&self) -> Result<PortMidiDeviceId> {
-        match unsafe { ffi::Pm_GetDefaultOutputDeviceID() } {
-            ffi::PM_NO_DEVICE => Err(Error::NoDefaultDevice),
-            id => Ok(id),
-        }
+        self.inner
+            .backend
+            .default_output_device_id()
+            .ok_or(Error::NoDefaultDevice)
     }
 
     /// Returns the `DeviceInfo` for the given device id or an `Error::PortMidi(_)` if
     /// the given id is invalid.
     pub fn device(&self, id: PortMidiDeviceId) -> Result<DeviceInfo> {
-        DeviceInfo::new(id)
+        match self.inner.backend.device_info(id) {
+            Some(description) => Ok(DeviceInfo::from_description(id, description)),
+            None => Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId)),
+        }
     }
 
     /// Returns a `Vec<DeviceInfo>` containing all known device infos.
@@ -71,6 +173,42 @@ impl PortMidi {
         Ok(devices)
     }
 
+    /// Returns the input and output devices that belong to the same physical device,
+    /// i.e. have the same name and interface.
+    pub fn device_pairs(&self) -> Result<Vec<DevicePair>> {
+        Ok(duplex::pair_devices(&self.devices()?))
+    }
+
+    /// Returns the input device that matches `query` best, see `DeviceQuery`.
+    /// If no device matches, an `Error::DeviceNotFound` listing the input devices is returned.
+    pub fn find_input(&self, query: &DeviceQuery) -> Result<DeviceInfo> {
+        self.find(query, Direction::Input)
+    }
+
+    /// Returns the output device that matches `query` best, see `DeviceQuery`.
+    /// If no device matches, an `Error::DeviceNotFound` listing the output devices is returned.
+    pub fn find_output(&self, query: &DeviceQuery) -> Result<DeviceInfo> {
+        self.find(query, Direction::Output)
+    }
+
+    fn find(&self, query: &DeviceQuery, direction: Direction) -> Result<DeviceInfo> {
+        let devices: Vec<DeviceInfo> = self
+            .devices()?
+            .into_iter()
+            .filter(|device| device.direction() == direction)
+            .collect();
+        match query.best_match(&devices) {
+            Some(device) => Ok(device.clone()),
+            None => Err(Error::DeviceNotFound {
+                query: query.to_string(),
+                candidates: devices
+                    .iter()
+                    .map(|device| format!("\"{}\" ({})", device.name(), device.interface()))
+                    .collect(),
+            }),
+        }
+    }
+
     /// Creates an `InputPort` instance with the given buffer size for the default input device.
     pub fn default_input_port(&self, buffer_size: usize) -> Result<InputPort> {
         let info = self
@@ -89,6 +227,20 @@ impl PortMidi {
         }
     }
 
+    /// Creates an `InputPort` instance for the given device and `InputPortOptions`.
+    /// If the given device is not an input device an `Error::NotAnInputDevice` is returned.
+    pub fn input_port_with(
+        &self,
+        device: DeviceInfo,
+        options: InputPortOptions,
+    ) -> Result<InputPort> {
+        if device.is_input() {
+            InputPort::with_options(self, device, options)
+        } else {
+            Err(Error::NotAnInputDevice)
+        }
+    }
+
     /// Creates an `OutputPort` instance with the given buffer size for the default output device.
     pub fn default_output_port(&self, buffer_size: usize) -> Result<OutputPort> {
         let info = self
@@ -107,21 +259,128 @@ impl PortMidi {
         }
     }
 
-    /// Creates a virtual output device for the lifetime of the PortMidi instance.
-    /// Returns the device info of the created device or throws an Error.
+    /// Creates an `OutputPort` instance for the given device and `OutputPortOptions`.
+    /// Use this to open a port with a latency, so that the timestamps of written events
+    /// are honored by PortMidi.
+    /// If the given device is not an output device an `Error::NotAnOutputDevice` is returned.
+    pub fn output_port_with(
+        &self,
+        device: DeviceInfo,
+        options: OutputPortOptions,
+    ) -> Result<OutputPort> {
+        if device.is_output() {
+            OutputPort::with_options(self, device, options)
+        } else {
+            Err(Error::NotAnOutputDevice)
+        }
+    }
+
+    /// Creates a `DuplexPort` for both devices of the pair with the given buffer size.
+    pub fn duplex_port(&self, pair: DevicePair, buffer_size: usize) -> Result<DuplexPort> {
+        DuplexPort::new(self, pair, buffer_size)
+    }
+
+    /// Creates a virtual input device owned by the context, see `VirtualDevice`.
+    /// Returns an `Error::DuplicateVirtualName` if the name is already in use and an
+    /// `Error::InvalidName` if it is empty or contains a NUL byte.
     pub fn create_virtual_input(&self, name: &str) -> Result<VirtualDevice> {
-        VirtualDevice::new(name, Direction::Input)
+        self.create_virtual_input_with(name, VirtualDeviceOptions::new())
     }
 
-    /// Creates a virtual input device for the lifetime of the PortMidi instance.
-    /// Returns the device info of the created device or throws an Error.
+    /// Creates a virtual output device owned by the context, see `create_virtual_input`.
     pub fn create_virtual_output(&self, name: &str) -> Result<VirtualDevice> {
-        VirtualDevice::new(name, Direction::Output)
+        self.create_virtual_output_with(name, VirtualDeviceOptions::new())
+    }
+
+    /// Creates a virtual input device with the given `VirtualDeviceOptions`.
+    pub fn create_virtual_input_with(
+        &self,
+        name: &str,
+        options: VirtualDeviceOptions,
+    ) -> Result<VirtualDevice> {
+        self.create_virtual_device(name, Direction::Input, &options)
+    }
+
+    /// Creates a virtual output device with the given `VirtualDeviceOptions`.
+    pub fn create_virtual_output_with(
+        &self,
+        name: &str,
+        options: VirtualDeviceOptions,
+    ) -> Result<VirtualDevice> {
+        self.create_virtual_device(name, Direction::Output, &options)
+    }
+
+    fn create_virtual_device(
+        &self,
+        name: &str,
+        direction: Direction,
+        options: &VirtualDeviceOptions,
+    ) -> Result<VirtualDevice> {
+        if name.is_empty() || name.contains('\0') {
+            return Err(Error::InvalidName(name.to_owned()));
+        }
+        let mut devices = self.lock_devices();
+        let backend = &self.inner.backend;
+        let mut result = backend.create_virtual_device(name, options.interface(), direction);
+        if options.unique_name() {
+            for suffix in 2..=MAX_UNIQUE_NAME_SUFFIX {
+                match result {
+                    Err(Error::DuplicateVirtualName(_)) => {}
+                    _ => break,
+                }
+                let unique_name = format!("{} {}", name, suffix);
+                result =
+                    backend.create_virtual_device(&unique_name, options.interface(), direction);
+            }
+        }
+        let info = self.device(result?)?;
+        devices.count = self.inner.backend.count_devices()?;
+        devices.virtual_devices.push(info.clone());
+        Ok(VirtualDevice::from_context(self.clone(), info))
+    }
+
+    /// Returns the number of virtual devices created with the context that haven't
+    /// been deleted.
+    pub fn virtual_device_count(&self) -> usize {
+        self.lock_devices().virtual_devices.len()
+    }
+
+    /// Returns the virtual devices created with the context that haven't been deleted.
+    pub fn virtual_devices(&self) -> Vec<VirtualDevice> {
+        self.lock_devices()
+            .virtual_devices
+            .iter()
+            .map(|info| VirtualDevice::from_context(self.clone(), info.clone()))
+            .collect()
+    }
+
+    /// Deletes a virtual device created with the context.
+    /// Returns an `Error::PortMidi(PmError::PmInvalidDeviceId)` if the id doesn't belong
+    /// to such a device, and an `Error::PortMidi(_)` if the device is still open.
+    pub fn delete_virtual_device(&self, id: PortMidiDeviceId) -> Result<()> {
+        let mut devices = self.lock_devices();
+        let index = devices
+            .virtual_devices
+            .iter()
+            .position(|info| info.id() == id)
+            .ok_or(Error::PortMidi(ffi::PmError::PmInvalidDeviceId))?;
+        self.inner.backend.delete_virtual_device(id)?;
+        devices.virtual_devices.remove(index);
+        Ok(())
     }
 }
-impl Drop for PortMidi {
+impl Drop for Context {
     fn drop(&mut self) {
-        Result::from(unsafe { ffi::Pm_Terminate() })
+        let devices = self
+            .devices
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        // use `delete_virtual_device` to handle errors
+        for info in devices.virtual_devices.drain(..) {
+            let _ = self.backend.delete_virtual_device(info.id());
+        }
+        self.backend
+            .terminate()
             .map_err(|err| println!("Could not terminate: {}", err))
             .unwrap();
     }
@@ -1,3 +1,4 @@
+use backend::{Backend, DeviceDescription, NativeBackend};
 use ffi;
 use std::fmt;
 use types::*;
@@ -10,34 +11,44 @@ pub enum Direction {
 }
 
 /// Represents a PortMidi device.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DeviceInfo {
     id: PortMidiDeviceId,
     /// The device name
     name: String,
     /// Event direction
     dir: Direction,
+    /// The underlying MIDI API
+    interface: String,
+    /// `true` if a port of the device was open when the info was obtained
+    opened: bool,
 }
 impl DeviceInfo {
     /// Creates a new `DeviceInfo` instance for the given device id.
     /// Returns an `Error::PortMidi(_)` if the given id is invalid.
+    ///
+    /// The device is looked up in the PortMidi C library, use `PortMidi::device`
+    /// to look it up in the backend of a context.
     pub fn new(id: PortMidiDeviceId) -> Result<Self> {
-        let dev_inf_ptr = unsafe { ffi::Pm_GetDeviceInfo(id) };
-        if dev_inf_ptr.is_null() {
-            Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId))
-        } else {
-            let name = unsafe { ffi::ptr_to_string((*dev_inf_ptr).name).unwrap() };
-            let direction = if unsafe { (*dev_inf_ptr).input != 0 } {
+        #[cfg(feature = "dynamic")]
+        ::library::load()?;
+        match NativeBackend.device_info(id) {
+            Some(description) => Ok(DeviceInfo::from_description(id, description)),
+            None => Err(Error::PortMidi(ffi::PmError::PmInvalidDeviceId)),
+        }
+    }
+
+    pub(crate) fn from_description(id: PortMidiDeviceId, description: DeviceDescription) -> Self {
+        DeviceInfo {
+            id,
+            name: description.name,
+            interface: description.interface,
+            opened: description.opened,
+            dir: if description.input {
                 Direction::Input
             } else {
                 Direction::Output
-            };
-
-            Ok(DeviceInfo {
-                id,
-                name,
-                dir: direction,
-            })
+            },
         }
     }
 
@@ -59,6 +70,16 @@ impl DeviceInfo {
         &self.name
     }
 
+    /// Returns the underlying MIDI API, e.g. `ALSA`, `CoreMIDI` or `MMSystem`.
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Returns `true` if a port of the device was open when the info was obtained.
+    pub fn is_opened(&self) -> bool {
+        self.opened
+    }
+
     /// Returns the device event direction.
     pub fn direction(&self) -> Direction {
         self.dir
@@ -0,0 +1,144 @@
+use context::PortMidi;
+use device::DeviceInfo;
+use ffi;
+use io::{InputPort, OutputPort};
+use std::thread;
+use std::time::{Duration, Instant};
+use sysex::{InputMessage, SysExReader, DEFAULT_MAX_SYSEX_LEN};
+use types::*;
+
+/// How long `DuplexPort::request` sleeps while no message is available.
+const REQUEST_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The input and output device of the same physical device, see `PortMidi::device_pairs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DevicePair {
+    pub input: DeviceInfo,
+    pub output: DeviceInfo,
+}
+impl DevicePair {
+    /// Returns the name both devices share.
+    pub fn name(&self) -> &str {
+        self.input.name()
+    }
+
+    /// Returns the MIDI API both devices share.
+    pub fn interface(&self) -> &str {
+        self.input.interface()
+    }
+}
+
+/// Groups the input and output devices with the same name and interface into pairs.
+/// Devices with the same name are paired in the order of their ids.
+pub(crate) fn pair_devices(devices: &[DeviceInfo]) -> Vec<DevicePair> {
+    let mut outputs: Vec<&DeviceInfo> = devices.iter().filter(|d| d.is_output()).collect();
+    let mut pairs = Vec::new();
+    for input in devices.iter().filter(|d| d.is_input()) {
+        let position = outputs.iter().position(|output| {
+            output.name() == input.name() && output.interface() == input.interface()
+        });
+        if let Some(position) = position {
+            pairs.push(DevicePair {
+                input: input.clone(),
+                output: outputs.remove(position).clone(),
+            });
+        }
+    }
+    pairs
+}
+
+/// An `InputPort` and an `OutputPort` of a `DevicePair`, for request/response
+/// protocols like an identity request or a SysEx dump.
+///
+/// Received SysEx messages are reassembled, like with a `SysExReader`.
+///
+/// ```no_run
+/// # use portmidi::{InputMessage, PortMidi};
+/// # use std::time::Duration;
+/// let context = PortMidi::new().unwrap();
+/// let pair = context.device_pairs().unwrap().remove(0);
+/// let mut port = context.duplex_port(pair, 1024).unwrap();
+/// let identity_request = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+/// let reply = port.request(&identity_request, Duration::from_secs(1), |message| match *message {
+///     InputMessage::SysEx(ref sysex) => sysex.data.starts_with(&[0xF0, 0x7E, 0x7F, 0x06, 0x02]),
+///     _ => false,
+/// });
+/// println!("{:?}", reply.unwrap());
+/// ```
+pub struct DuplexPort {
+    input: SysExReader,
+    output: OutputPort,
+}
+impl DuplexPort {
+    /// Opens both devices of the pair with the given buffer size.
+    pub fn new(context: &PortMidi, pair: DevicePair, buffer_size: usize) -> Result<Self> {
+        let input = InputPort::new(context, pair.input, buffer_size)?;
+        let output = OutputPort::new(context, pair.output, buffer_size)?;
+        Ok(DuplexPort::from_parts(input, output))
+    }
+
+    /// Combines an already opened input and output port.
+    pub fn from_parts(input: InputPort, output: OutputPort) -> Self {
+        DuplexPort {
+            input: SysExReader::new(input, DEFAULT_MAX_SYSEX_LEN),
+            output,
+        }
+    }
+
+    /// Returns the input port.
+    pub fn input(&self) -> &InputPort {
+        self.input.port()
+    }
+
+    /// Returns the output port.
+    pub fn output(&mut self) -> &mut OutputPort {
+        &mut self.output
+    }
+
+    /// Returns the next received message if one is available, see `SysExReader::read`.
+    pub fn read(&mut self) -> Result<Option<InputMessage>> {
+        self.input.read()
+    }
+
+    /// Writes a single message to the output.
+    pub fn write_message<T: Into<MidiMessage>>(&mut self, midi_message: T) -> Result<()> {
+        self.output.write_message(midi_message)
+    }
+
+    /// Writes an EOX-terminated SysEx message to the output.
+    pub fn write_sysex(&self, timestamp: ffi::PmTimestamp, msg: &[u8]) -> Result<()> {
+        self.output.write_sysex(timestamp, msg)
+    }
+
+    /// Writes the SysEx message `request` and waits at most `timeout` for a received
+    /// message for which `is_response` returns `true`. Other received messages are
+    /// discarded, `None` is returned on timeout.
+    pub fn request<F>(
+        &mut self,
+        request: &[u8],
+        timeout: Duration,
+        mut is_response: F,
+    ) -> Result<Option<InputMessage>>
+    where
+        F: FnMut(&InputMessage) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        self.write_sysex(0, request)?;
+        loop {
+            match self.read()? {
+                Some(message) => {
+                    if is_response(&message) {
+                        return Ok(Some(message));
+                    }
+                }
+                None if Instant::now() >= deadline => return Ok(None),
+                None => thread::sleep(REQUEST_POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Returns the input and output port, discarding pending messages.
+    pub fn into_parts(self) -> (InputPort, OutputPort) {
+        (self.input.into_inner(), self.output)
+    }
+}
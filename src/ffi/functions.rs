@@ -9,12 +9,50 @@
 use ffi::types::*;
 use std::os::raw::{c_char, c_int, c_uchar, c_void};
 
-#[allow(dead_code)]
-#[link(name = "portmidi")]
-extern "C" {
+/// Declares the PortMidi functions, linked by the build script.
+#[cfg(not(feature = "dynamic"))]
+macro_rules! portmidi_functions {
+    ($($vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)*) $(-> $ret:ty)*;)*) => {
+        #[allow(dead_code)]
+        extern "C" {
+            $($vis fn $name($($arg: $ty),*) $(-> $ret)*;)*
+        }
+    };
+}
+
+/// Declares the PortMidi functions as wrappers that call through the function table
+/// loaded by the `library` module.
+#[cfg(feature = "dynamic")]
+macro_rules! portmidi_functions {
+    ($($vis:vis fn $name:ident($($arg:ident: $ty:ty),* $(,)*) $(-> $ret:ty)*;)*) => {
+        /// The PortMidi functions resolved from a shared library.
+        #[allow(non_snake_case)]
+        pub struct Api {
+            $($name: unsafe extern "C" fn($($ty),*) $(-> $ret)*,)*
+        }
+        impl Api {
+            /// Resolves all functions, returns the name of the first missing symbol on error.
+            pub unsafe fn load(library: &::libloading::Library) -> Result<Api, &'static str> {
+                Ok(Api {
+                    $($name: *library
+                        .get(concat!(stringify!($name), "\0").as_bytes())
+                        .map_err(|_| stringify!($name))?,)*
+                })
+            }
+        }
+        $(
+            #[allow(dead_code, non_snake_case)]
+            $vis unsafe fn $name($($arg: $ty),*) $(-> $ret)* {
+                (::library::api().$name)($($arg),*)
+            }
+        )*
+    };
+}
+
+portmidi_functions! {
     pub fn Pm_Initialize() -> PmError;
     pub fn Pm_Terminate() -> PmError;
-    fn Pm_HasHostError(stream: *const PortMidiStream) -> c_int;
+    pub fn Pm_HasHostError(stream: *const PortMidiStream) -> c_int;
     pub fn Pm_GetErrorText(errorCode: PmError) -> *const c_char;
     pub fn Pm_GetHostErrorText(msg: *mut c_char, len: c_int);
     pub fn Pm_CountDevices() -> c_int;
@@ -26,7 +64,7 @@ extern "C" {
         inputDevice: PmDeviceId,
         inputDriverInfo: *const c_void,
         bufferSize: i32,
-        time_proc: *const c_void,
+        time_proc: Option<PmTimeProcPtr>,
         time_info: *const c_void,
     ) -> PmError;
     pub fn Pm_OpenOutput(
@@ -34,7 +72,7 @@ extern "C" {
         outputDevice: PmDeviceId,
         inputDriverInfo: *const c_void,
         bufferSize: i32,
-        time_proc: *const c_void,
+        time_proc: Option<PmTimeProcPtr>,
         time_info: *const c_void,
         latency: i32,
     ) -> PmError;
@@ -50,9 +88,11 @@ extern "C" {
     ) -> PmError;
     pub fn Pm_DeleteVirtualDevice(device: PmDeviceId) -> PmError;
     pub fn Pm_Read(stream: *const PortMidiStream, buffer: *mut PmEvent, length: c_int) -> c_int;
-    fn Pm_Abort(stream: *const PortMidiStream) -> PmError;
+    pub fn Pm_Abort(stream: *const PortMidiStream) -> PmError;
     pub fn Pm_Close(stream: *const PortMidiStream) -> PmError;
     pub fn Pm_Poll(stream: *const PortMidiStream) -> PmError;
+    pub fn Pm_SetFilter(stream: *const PortMidiStream, filters: i32) -> PmError;
+    pub fn Pm_SetChannelMask(stream: *const PortMidiStream, mask: c_int) -> PmError;
     pub fn Pm_Write(
         stream: *const PortMidiStream,
         buffer: *const PmEvent,
@@ -68,4 +108,13 @@ extern "C" {
         when: PmTimestamp,
         msg: *const c_uchar,
     ) -> PmError;
+
+    pub fn Pt_Start(
+        resolution: c_int,
+        callback: Option<PtCallback>,
+        userData: *mut c_void,
+    ) -> PtError;
+    pub fn Pt_Stop() -> PtError;
+    pub fn Pt_Started() -> c_int;
+    pub fn Pt_Time() -> PmTimestamp;
 }
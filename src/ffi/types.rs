@@ -9,9 +9,64 @@ pub type PortMidiStream = c_void;
 pub type PmMessage = c_uint;
 
 pub type PmTimestamp = u32;
+pub type PmTimeProcPtr = extern "C" fn(time_info: *const c_void) -> PmTimestamp;
 pub const PM_NO_DEVICE: PmDeviceId = -1;
+/// returned by `Pm_CreateVirtualInput/Output` if `interf` is not supported
+pub const PM_INTERFACE_NOT_SUPPORTED: c_int = -9990;
+/// returned by `Pm_CreateVirtualInput/Output` if the name is already in use
+pub const PM_NAME_CONFLICT: c_int = -9989;
 pub const MIDI_EOX: u8 = 0xf7;
 
+/// filter active sensing messages (0xFE)
+pub const PM_FILT_ACTIVE: i32 = 1 << 0x0E;
+/// filter system exclusive messages (0xF0)
+pub const PM_FILT_SYSEX: i32 = 1 << 0x00;
+/// filter MIDI clock message (0xF8)
+pub const PM_FILT_CLOCK: i32 = 1 << 0x08;
+/// filter play messages (start 0xFA, stop 0xFC, continue 0xFB)
+pub const PM_FILT_PLAY: i32 = (1 << 0x0A) | (1 << 0x0C) | (1 << 0x0B);
+/// filter tick messages (0xF9)
+pub const PM_FILT_TICK: i32 = 1 << 0x09;
+/// filter undefined FD messages
+pub const PM_FILT_FD: i32 = 1 << 0x0D;
+/// filter undefined real-time messages
+pub const PM_FILT_UNDEFINED: i32 = PM_FILT_FD;
+/// filter reset messages (0xFF)
+pub const PM_FILT_RESET: i32 = 1 << 0x0F;
+/// filter all real-time messages
+pub const PM_FILT_REALTIME: i32 = PM_FILT_ACTIVE
+    | PM_FILT_SYSEX
+    | PM_FILT_CLOCK
+    | PM_FILT_PLAY
+    | PM_FILT_UNDEFINED
+    | PM_FILT_RESET
+    | PM_FILT_TICK;
+/// filter note-on and note-off (0x90-0x9F and 0x80-0x8F)
+pub const PM_FILT_NOTE: i32 = (1 << 0x19) | (1 << 0x18);
+/// filter channel aftertouch (most midi controllers use this) (0xD0-0xDF)
+pub const PM_FILT_CHANNEL_AFTERTOUCH: i32 = 1 << 0x1D;
+/// per-note aftertouch (0xA0-0xAF)
+pub const PM_FILT_POLY_AFTERTOUCH: i32 = 1 << 0x1A;
+/// filter both channel and poly aftertouch
+pub const PM_FILT_AFTERTOUCH: i32 = PM_FILT_CHANNEL_AFTERTOUCH | PM_FILT_POLY_AFTERTOUCH;
+/// Program changes (0xC0-0xCF)
+pub const PM_FILT_PROGRAM: i32 = 1 << 0x1C;
+/// Control Changes (CC's) (0xB0-0xBF)
+pub const PM_FILT_CONTROL: i32 = 1 << 0x1B;
+/// Pitch Bender (0xE0-0xEF)
+pub const PM_FILT_PITCHBEND: i32 = 1 << 0x1E;
+/// MIDI Time Code (0xF1)
+pub const PM_FILT_MTC: i32 = 1 << 0x01;
+/// Song Position (0xF2)
+pub const PM_FILT_SONG_POSITION: i32 = 1 << 0x02;
+/// Song Select (0xF3)
+pub const PM_FILT_SONG_SELECT: i32 = 1 << 0x03;
+/// Tuning request (0xF6)
+pub const PM_FILT_TUNE: i32 = 1 << 0x06;
+/// All System Common messages (mtc, song position, song select, tune request)
+pub const PM_FILT_SYSTEMCOMMON: i32 =
+    PM_FILT_MTC | PM_FILT_SONG_POSITION | PM_FILT_SONG_SELECT | PM_FILT_TUNE;
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct PmEvent {
@@ -41,6 +96,8 @@ pub struct PmDeviceInfo {
     pub output: c_int,
     /// used by generic PortMidi code to do error checking on arguments
     pub opened: c_int,
+    /// true iff the device was created with `Pm_CreateVirtualInput/Output`
+    pub is_virtual: c_int,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -69,30 +126,58 @@ pub enum PmError {
     PmInternalError = -9993,
     /// buffer is already as large as it can be
     PmBufferMaxSize = -9992,
+    /// the function is not implemented, e.g. virtual devices on some platforms
+    PmNotImplemented = -9991,
 }
 impl fmt::Display for PmError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut host_error_text: [c_char; 1024] = [0; 1024];
-        let str_ptr = match *self {
-            PmError::PmHostError => unsafe {
-                ffi::Pm_GetHostErrorText(
-                    host_error_text.as_mut_ptr(),
-                    host_error_text.len() as c_int,
-                );
-                host_error_text.as_ptr()
-            },
-            _ => unsafe { ffi::Pm_GetErrorText(*self) },
-        };
+        // the host error text is taken where the error occurs, see `Error::Host`
+        #[cfg(feature = "dynamic")]
+        {
+            if ::library::load().is_err() {
+                return write!(f, "{:?}", self);
+            }
+        }
+        let str_ptr = unsafe { ffi::Pm_GetErrorText(*self) };
         write!(f, "{}", ffi::ptr_to_string(str_ptr).unwrap())
     }
 }
+pub type PtCallback = extern "C" fn(timestamp: PmTimestamp, user_data: *mut c_void);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum PtError {
+    /// success
+    PtNoError = 0,
+    /// a system-specific error occurred
+    PtHostError = -10000,
+    /// cannot start timer because it is already started
+    PtAlreadyStarted = -9999,
+    /// cannot stop timer because it is already stopped
+    PtAlreadyStopped = -9998,
+    /// memory could not be allocated
+    PtInsufficientMemory = -9997,
+}
+impl fmt::Display for PtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            PtError::PtNoError => "PortTime: `No error'",
+            PtError::PtHostError => "PortTime: `Host error'",
+            PtError::PtAlreadyStarted => "PortTime: `Timer already started'",
+            PtError::PtAlreadyStopped => "PortTime: `Timer already stopped'",
+            PtError::PtInsufficientMemory => "PortTime: `Insufficient memory'",
+        };
+        write!(f, "{}", text)
+    }
+}
+
 pub trait MaybeError<T> {
     fn try_from(err_code: T) -> Result<T, PmError>;
 }
 impl MaybeError<c_int> for PmError {
     fn try_from(err_code: c_int) -> Result<c_int, PmError> {
         match err_code {
-            -10_000..=-9992 | 0 => unsafe { Err(mem::transmute(err_code)) },
+            -10_000..=-9991 | 0 => unsafe { Err(mem::transmute(err_code)) },
             -9989 => Err(PmError::PmInvalidDeviceId),
             _ => Ok(err_code),
         }
@@ -0,0 +1,193 @@
+use ffi;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not, Sub};
+
+/// A set of message types that PortMidi drops before they reach the input buffer.
+///
+/// Filters can be combined with `|`, e.g. `InputFilter::ACTIVE | InputFilter::CLOCK`.
+/// The default filter only drops active sensing messages, like PortMidi does.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InputFilter(i32);
+impl InputFilter {
+    /// Active sensing messages (0xFE).
+    pub const ACTIVE: InputFilter = InputFilter(ffi::PM_FILT_ACTIVE);
+    /// System exclusive messages (0xF0).
+    pub const SYSEX: InputFilter = InputFilter(ffi::PM_FILT_SYSEX);
+    /// MIDI clock messages (0xF8).
+    pub const CLOCK: InputFilter = InputFilter(ffi::PM_FILT_CLOCK);
+    /// Play messages (start 0xFA, stop 0xFC, continue 0xFB).
+    pub const PLAY: InputFilter = InputFilter(ffi::PM_FILT_PLAY);
+    /// Tick messages (0xF9).
+    pub const TICK: InputFilter = InputFilter(ffi::PM_FILT_TICK);
+    /// Undefined real-time messages (0xFD).
+    pub const UNDEFINED: InputFilter = InputFilter(ffi::PM_FILT_UNDEFINED);
+    /// Reset messages (0xFF).
+    pub const RESET: InputFilter = InputFilter(ffi::PM_FILT_RESET);
+    /// All real-time messages and system exclusive messages.
+    pub const REALTIME: InputFilter = InputFilter(ffi::PM_FILT_REALTIME);
+    /// Note on and note off messages (0x80-0x9F).
+    pub const NOTE: InputFilter = InputFilter(ffi::PM_FILT_NOTE);
+    /// Channel aftertouch messages (0xD0-0xDF).
+    pub const CHANNEL_AFTERTOUCH: InputFilter = InputFilter(ffi::PM_FILT_CHANNEL_AFTERTOUCH);
+    /// Polyphonic aftertouch messages (0xA0-0xAF).
+    pub const POLY_AFTERTOUCH: InputFilter = InputFilter(ffi::PM_FILT_POLY_AFTERTOUCH);
+    /// Channel and polyphonic aftertouch messages.
+    pub const AFTERTOUCH: InputFilter = InputFilter(ffi::PM_FILT_AFTERTOUCH);
+    /// Program change messages (0xC0-0xCF).
+    pub const PROGRAM: InputFilter = InputFilter(ffi::PM_FILT_PROGRAM);
+    /// Control change messages (0xB0-0xBF).
+    pub const CONTROL: InputFilter = InputFilter(ffi::PM_FILT_CONTROL);
+    /// Pitch bend messages (0xE0-0xEF).
+    pub const PITCHBEND: InputFilter = InputFilter(ffi::PM_FILT_PITCHBEND);
+    /// MIDI time code messages (0xF1).
+    pub const MTC: InputFilter = InputFilter(ffi::PM_FILT_MTC);
+    /// Song position messages (0xF2).
+    pub const SONG_POSITION: InputFilter = InputFilter(ffi::PM_FILT_SONG_POSITION);
+    /// Song select messages (0xF3).
+    pub const SONG_SELECT: InputFilter = InputFilter(ffi::PM_FILT_SONG_SELECT);
+    /// Tune request messages (0xF6).
+    pub const TUNE: InputFilter = InputFilter(ffi::PM_FILT_TUNE);
+    /// All system common messages (time code, song position, song select, tune request).
+    pub const SYSTEMCOMMON: InputFilter = InputFilter(ffi::PM_FILT_SYSTEMCOMMON);
+
+    /// Returns a filter that lets all messages pass.
+    pub fn empty() -> Self {
+        InputFilter(0)
+    }
+
+    /// Returns `true` if no message type is filtered.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all message types of `other` are filtered by `self`.
+    pub fn contains(&self, other: InputFilter) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the raw PortMidi filter bits.
+    pub fn bits(&self) -> i32 {
+        self.0
+    }
+}
+impl Default for InputFilter {
+    fn default() -> Self {
+        InputFilter::ACTIVE
+    }
+}
+impl BitOr for InputFilter {
+    type Output = InputFilter;
+
+    fn bitor(self, other: InputFilter) -> InputFilter {
+        InputFilter(self.0 | other.0)
+    }
+}
+impl BitOrAssign for InputFilter {
+    fn bitor_assign(&mut self, other: InputFilter) {
+        self.0 |= other.0;
+    }
+}
+impl BitAnd for InputFilter {
+    type Output = InputFilter;
+
+    fn bitand(self, other: InputFilter) -> InputFilter {
+        InputFilter(self.0 & other.0)
+    }
+}
+impl Sub for InputFilter {
+    type Output = InputFilter;
+
+    fn sub(self, other: InputFilter) -> InputFilter {
+        InputFilter(self.0 & !other.0)
+    }
+}
+impl fmt::Debug for InputFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InputFilter({:#010x})", self.0)
+    }
+}
+
+/// A set of MIDI channels whose channel messages are passed through by an `InputPort`.
+///
+/// Channels are zero based (`0..=15`). The default mask passes all channels.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChannelMask(u16);
+impl ChannelMask {
+    /// A mask that passes all channels.
+    pub const ALL: ChannelMask = ChannelMask(0xFFFF);
+    /// A mask that blocks all channels.
+    pub const NONE: ChannelMask = ChannelMask(0);
+
+    /// Returns a mask that only passes the given channel.
+    /// Channels above 15 are ignored.
+    pub fn channel(channel: u8) -> Self {
+        ChannelMask::NONE.with(channel)
+    }
+
+    /// Returns a mask that passes all channels of the iterator.
+    /// Channels above 15 are ignored.
+    pub fn channels<I: IntoIterator<Item = u8>>(channels: I) -> Self {
+        channels
+            .into_iter()
+            .fold(ChannelMask::NONE, |mask, channel| mask.with(channel))
+    }
+
+    /// Returns a copy of the mask that also passes the given channel.
+    pub fn with(self, channel: u8) -> Self {
+        if channel < 16 {
+            ChannelMask(self.0 | 1 << channel)
+        } else {
+            self
+        }
+    }
+
+    /// Returns a copy of the mask that blocks the given channel.
+    pub fn without(self, channel: u8) -> Self {
+        if channel < 16 {
+            ChannelMask(self.0 & !(1 << channel))
+        } else {
+            self
+        }
+    }
+
+    /// Returns `true` if the mask passes the given channel.
+    pub fn contains(&self, channel: u8) -> bool {
+        channel < 16 && self.0 & 1 << channel != 0
+    }
+
+    /// Returns the raw PortMidi channel mask bits.
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+}
+impl Default for ChannelMask {
+    fn default() -> Self {
+        ChannelMask::ALL
+    }
+}
+impl BitOr for ChannelMask {
+    type Output = ChannelMask;
+
+    fn bitor(self, other: ChannelMask) -> ChannelMask {
+        ChannelMask(self.0 | other.0)
+    }
+}
+impl BitAnd for ChannelMask {
+    type Output = ChannelMask;
+
+    fn bitand(self, other: ChannelMask) -> ChannelMask {
+        ChannelMask(self.0 & other.0)
+    }
+}
+impl Not for ChannelMask {
+    type Output = ChannelMask;
+
+    fn not(self) -> ChannelMask {
+        ChannelMask(!self.0)
+    }
+}
+impl fmt::Debug for ChannelMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChannelMask({:#018b})", self.0)
+    }
+}
@@ -1,82 +1,216 @@
+use backend::Stream;
 use context::PortMidi;
 use device::DeviceInfo;
 use ffi;
-use ffi::MaybeError;
-use std::marker::Send;
-use std::os::raw::c_int;
-use std::ptr;
+use filter::{ChannelMask, InputFilter};
+use message::MessageEvent;
+use porttime;
+use std::convert::TryInto;
+use std::fmt;
+use std::slice;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::vec;
+use time::TimeSource;
 use types::*;
 
+/// Options used to open an `InputPort`.
+#[derive(Clone)]
+pub struct InputPortOptions {
+    buffer_size: usize,
+    time_source: Option<Arc<dyn TimeSource>>,
+}
+impl InputPortOptions {
+    /// Creates options for the given buffer size using PortTime for timestamps.
+    pub fn new(buffer_size: usize) -> Self {
+        InputPortOptions {
+            buffer_size,
+            time_source: None,
+        }
+    }
+
+    /// Sets the number of events that can be buffered by the port.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the `TimeSource` used to timestamp incoming events instead of PortTime.
+    pub fn with_time_source<T: TimeSource + 'static>(mut self, time_source: T) -> Self {
+        self.time_source = Some(Arc::new(time_source));
+        self
+    }
+
+    /// Returns the buffer size.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Returns the custom `TimeSource`, `None` means PortTime is used.
+    pub fn time_source(&self) -> Option<&Arc<dyn TimeSource>> {
+        self.time_source.as_ref()
+    }
+}
+impl Default for InputPortOptions {
+    fn default() -> Self {
+        InputPortOptions::new(1024)
+    }
+}
+impl fmt::Debug for InputPortOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InputPortOptions")
+            .field("buffer_size", &self.buffer_size)
+            .field("time_source", &self.time_source.is_some())
+            .finish()
+    }
+}
+
 /// Represents the input port of a PortMidi device.
-pub struct InputPort<'a> {
-    stream: *const ffi::PortMidiStream,
+pub struct InputPort {
+    stream: Box<dyn Stream>,
     buffer_size: usize,
-    _context: &'a PortMidi, // Used for lifetime pinning
+    // keeps the context initialized while the port is open
+    _context: PortMidi,
     device: DeviceInfo,
+    filter: InputFilter,
+    channel_mask: ChannelMask,
+    // the events returned by `drain`, reused across calls
+    events: Vec<MidiEvent>,
 }
-impl<'a> InputPort<'a> {
+impl InputPort {
     /// Construct a new `InputPort` for the given device and buffer size.
+    /// The port is opened with the default `InputFilter` (active sensing is dropped)
+    /// and a `ChannelMask` that passes all channels.
     ///
     /// If the `device` is not an input device an `Error::NotAnInputDevice` is returned.
-    pub fn new(context: &'a PortMidi, device: DeviceInfo, buffer_size: usize) -> Result<InputPort> {
+    pub fn new(context: &PortMidi, device: DeviceInfo, buffer_size: usize) -> Result<InputPort> {
+        InputPort::with_options(context, device, InputPortOptions::new(buffer_size))
+    }
+
+    /// Construct a new `InputPort` for the given device and `InputPortOptions`.
+    ///
+    /// If the `device` is not an input device an `Error::NotAnInputDevice` is returned.
+    pub fn with_options(
+        context: &PortMidi,
+        device: DeviceInfo,
+        options: InputPortOptions,
+    ) -> Result<InputPort> {
         if device.is_output() {
             return Err(Error::NotAnInputDevice);
         }
-        let raw_stream: *const ffi::PortMidiStream = ptr::null();
-        Result::from(unsafe {
-            ffi::Pm_OpenInput(
-                &raw_stream as *const *const _,
-                device.id(),
-                ptr::null(), // *inputDriverInfo, not needed for normal operation
-                buffer_size as c_int,
-                ptr::null(), // PmTimeProcPtr, a procedure that returns time in ms
-                ptr::null(),
-            ) // time_info, a pointer passed to the time procedure
-        })?;
-
-        Ok(InputPort {
-            stream: raw_stream,
-            buffer_size,
-            _context: context,
+        let stream = context.open_input(device.id(), &options)?;
+
+        let mut port = InputPort {
+            stream,
+            buffer_size: options.buffer_size,
+            _context: context.clone(),
             device,
-        })
+            filter: InputFilter::default(),
+            channel_mask: ChannelMask::default(),
+            events: Vec::new(),
+        };
+        port.set_filter(InputFilter::default())?;
+        port.set_channel_mask(ChannelMask::default())?;
+        Ok(port)
+    }
+
+    /// Sets the message types that are dropped by PortMidi before they reach the input buffer.
+    /// This replaces the current filter, `InputFilter::empty()` lets all messages pass.
+    /// Returns an `Error::PortMidi(_)` if something went wrong.
+    pub fn set_filter(&mut self, filter: InputFilter) -> Result<()> {
+        self.stream.set_filter(filter)?;
+        self.filter = filter;
+        Ok(())
+    }
+
+    /// Returns the current `InputFilter` of the port.
+    pub fn filter(&self) -> InputFilter {
+        self.filter
+    }
+
+    /// Sets the channels whose channel messages are passed through.
+    /// Returns an `Error::PortMidi(_)` if something went wrong.
+    pub fn set_channel_mask(&mut self, mask: ChannelMask) -> Result<()> {
+        self.stream.set_channel_mask(mask)?;
+        self.channel_mask = mask;
+        Ok(())
+    }
+
+    /// Returns the current `ChannelMask` of the port.
+    pub fn channel_mask(&self) -> ChannelMask {
+        self.channel_mask
     }
 
     /// Returns a `Vec<MidiEvent>` with at most `cnt` elements.
     /// If there was no Midi event available, `None` is returned.
     /// If PortMidi fails to read from the device an `Error::PortMidi(_)` is returned.
     pub fn read_n(&self, cnt: usize) -> Result<Option<Vec<MidiEvent>>> {
-        let read_cnt = if cnt > self.buffer_size {
-            self.buffer_size as c_int
-        } else {
-            cnt as c_int
-        };
-        let mut event_buffer = vec![ffi::PmEvent::default(); self.buffer_size];
-        let res = unsafe { ffi::Pm_Read(self.stream, event_buffer.as_mut_ptr(), read_cnt) };
-        match ffi::PmError::try_from(res) {
-            Ok(event_cnt) => {
-                let events = (0..event_cnt as usize)
-                    .map(|i| MidiEvent::from(event_buffer[i]))
-                    .collect::<Vec<MidiEvent>>();
+        let mut events = vec![MidiEvent::default(); cnt.min(self.buffer_size)];
+        match self.read_into(&mut events)? {
+            0 => Ok(None),
+            read_cnt => {
+                events.truncate(read_cnt);
                 Ok(Some(events))
             }
-            Err(ffi::PmError::PmNoError) => Ok(None),
-            Err(err) => Err(Error::PortMidi(err)),
         }
     }
 
+    /// Reads the available events into `events` without allocating and returns their
+    /// number, `0` means no event was available. At most `buffer_size` events are read.
+    /// If PortMidi fails to read from the device an `Error::PortMidi(_)` is returned.
+    ///
+    /// ```no_run
+    /// # use portmidi::{MidiEvent, PortMidi};
+    /// let context = PortMidi::new().unwrap();
+    /// let in_port = context.default_input_port(1024).unwrap();
+    /// let mut events = [MidiEvent::default(); 1024];
+    /// let read_cnt = in_port.read_into(&mut events).unwrap();
+    /// for event in &events[..read_cnt] {
+    ///     println!("{:?}", event);
+    /// }
+    /// ```
+    pub fn read_into(&self, events: &mut [MidiEvent]) -> Result<usize> {
+        let read_cnt = events.len().min(self.buffer_size);
+        self.stream.read(&mut events[..read_cnt])
+    }
+
+    /// Reads the available events, at most `buffer_size`, into storage owned by the port
+    /// and returns an iterator that removes them. The storage is reused, so only the
+    /// first call allocates.
+    /// If PortMidi fails to read from the device an `Error::PortMidi(_)` is returned.
+    pub fn drain(&mut self) -> Result<vec::Drain<'_, MidiEvent>> {
+        self.events.resize(self.buffer_size, MidiEvent::default());
+        let read_cnt = match self.stream.read(&mut self.events) {
+            Ok(read_cnt) => read_cnt,
+            Err(err) => {
+                self.events.clear();
+                return Err(err);
+            }
+        };
+        self.events.truncate(read_cnt);
+        Ok(self.events.drain(..))
+    }
+
     /// Reads a single `MidiEvent` if one is avaible.
     ///
     /// A `Result` of `None` means no event was available.
     pub fn read(&mut self) -> Result<Option<MidiEvent>> {
-        let mut event = ffi::PmEvent::default();
-        let read_cnt = 1;
-        let res = unsafe { ffi::Pm_Read(self.stream, &mut event, read_cnt) };
-        match ffi::PmError::try_from(res) {
-            Ok(0) => Ok(None),
-            Ok(_) => Ok(Some(MidiEvent::from(event))),
-            Err(ffi::PmError::PmNoError) => Ok(None),
-            Err(err) => Err(Error::PortMidi(err)),
+        let mut events = [MidiEvent::default()];
+        match self.read_into(&mut events)? {
+            0 => Ok(None),
+            _ => Ok(Some(events[0])),
+        }
+    }
+
+    /// Reads a single `MidiEvent` if one is available and decodes it into a typed `MessageEvent`.
+    ///
+    /// A `Result` of `None` means no event was available.
+    /// If the event can't be decoded an `Error::InvalidMessage(_)` is returned.
+    pub fn read_message(&mut self) -> Result<Option<MessageEvent>> {
+        match self.read()? {
+            Some(event) => Ok(Some(event.try_into()?)),
+            None => Ok(None),
         }
     }
 
@@ -84,86 +218,391 @@ impl<'a> InputPort<'a> {
     /// Returns `true` if there are events available, otherwise `false` is returned.
     /// If the polling fails an `Error::PortMidi(_)` is returned.
     pub fn poll(&self) -> Result<bool> {
-        let pm_error = unsafe { ffi::Pm_Poll(self.stream) };
-        match pm_error {
-            ffi::PmError::PmNoError => Ok(false),
-            ffi::PmError::PmGotData => Ok(true),
-            err => Err(Error::PortMidi(err)),
-        }
+        self.stream.poll()
+    }
+
+    /// Returns `true` if the host MIDI API has a pending error for this port, e.g. after
+    /// the device was disconnected. The next call that fails returns it as `Error::Host`.
+    pub fn has_host_error(&self) -> bool {
+        self.stream.has_host_error()
     }
 
     /// Returns the `DeviceInfo` of the Midi device that owns this port.
     pub fn device(&self) -> DeviceInfo {
         self.device.clone()
     }
+
+    /// Returns the number of events that can be buffered by the port.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
 }
-impl<'a> Drop for InputPort<'a> {
+impl Drop for InputPort {
     fn drop(&mut self) {
-        if let Err(err) = Result::from(unsafe { ffi::Pm_Close(self.stream) }) {
-            println!("{}", err);
+        // like `OutputPort`, errors on close are ignored instead of printed
+        let _ = self.stream.close();
+    }
+}
+
+pub(crate) const CONTROL_RESET_ALL_CONTROLLERS: u8 = 121;
+pub(crate) const CONTROL_ALL_NOTES_OFF: u8 = 123;
+
+/// How an `OutputPort` retries a write that failed with `PmError::PmBufferOverflow`,
+/// e.g. because the output buffer is full of events scheduled with a latency.
+///
+/// Before retry `n` (counting from `0`) the port sleeps `backoff * 2^n`, at most
+/// `max_backoff`. PortMidi stops a write at the first event that overflows without
+/// reporting which one it was, so a port with retries writes its events one at a
+/// time and only retries the event that overflowed, no event is sent twice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+impl RetryPolicy {
+    /// A policy that returns the overflow error without retrying, the default.
+    pub fn never() -> Self {
+        RetryPolicy::new(0, Duration::from_millis(0))
+    }
+
+    /// A policy that retries an event at most `max_retries` times, sleeping `backoff` before the
+    /// first retry and doubling it for every further retry.
+    pub fn new(max_retries: u32, backoff: Duration) -> Self {
+        RetryPolicy {
+            max_retries,
+            backoff,
+            max_backoff: DEFAULT_MAX_BACKOFF,
         }
     }
+
+    /// Sets the longest sleep between two retries, `DEFAULT_MAX_BACKOFF` by default.
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns the maximum number of retries.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Returns the sleep before retry `retry`, counting from `0`.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::never()
+    }
+}
+
+/// The longest sleep between two retries of a `RetryPolicy` unless set otherwise.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Options used to open an `OutputPort`.
+///
+/// ```no_run
+/// # use portmidi::{OutputPortOptions, PortMidi};
+/// let context = PortMidi::new().unwrap();
+/// let device = context.device(context.default_output_device_id().unwrap()).unwrap();
+/// // schedule events 20ms after their timestamp
+/// let options = OutputPortOptions::new(1024).with_latency(20);
+/// let out_port = context.output_port_with(device, options).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct OutputPortOptions {
+    buffer_size: usize,
+    latency: u32,
+    time_source: Option<Arc<dyn TimeSource>>,
+    retry_policy: RetryPolicy,
+    panic_on_close: bool,
+}
+impl OutputPortOptions {
+    /// Creates options for the given buffer size without latency, using PortTime
+    /// for scheduling.
+    pub fn new(buffer_size: usize) -> Self {
+        OutputPortOptions {
+            buffer_size,
+            latency: 0,
+            time_source: None,
+            retry_policy: RetryPolicy::never(),
+            panic_on_close: false,
+        }
+    }
+
+    /// Sets the number of events that can be buffered by the port.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the output latency in milliseconds.
+    ///
+    /// With a latency of `0` PortMidi ignores the timestamps of written events and
+    /// delivers them immediately. With a positive latency each event is delivered at
+    /// `timestamp + latency`, where timestamps are PortTime milliseconds.
+    /// PortMidi takes a signed latency, so it is clamped to `i32::MAX`.
+    pub fn with_latency(mut self, latency: u32) -> Self {
+        self.latency = latency.min(i32::MAX as u32);
+        self
+    }
+
+    /// Sets the `TimeSource` that event timestamps refer to instead of PortTime.
+    /// Only has an effect if a latency is set.
+    pub fn with_time_source<T: TimeSource + 'static>(mut self, time_source: T) -> Self {
+        self.time_source = Some(Arc::new(time_source));
+        self
+    }
+
+    /// Sets how writes that overflow the output buffer are retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// If `true`, All Notes Off and Reset All Controllers are sent on every channel
+    /// when the port is closed, see `OutputPort::send_panic`.
+    pub fn with_panic_on_close(mut self, panic_on_close: bool) -> Self {
+        self.panic_on_close = panic_on_close;
+        self
+    }
+
+    /// Returns the buffer size.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Returns the output latency in milliseconds.
+    pub fn latency(&self) -> u32 {
+        self.latency
+    }
+
+    /// Returns the custom `TimeSource`, `None` means PortTime is used.
+    pub fn time_source(&self) -> Option<&Arc<dyn TimeSource>> {
+        self.time_source.as_ref()
+    }
+
+    /// Returns the `RetryPolicy` for writes that overflow the output buffer.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    /// Returns `true` if the panic messages are sent when the port is closed.
+    pub fn panic_on_close(&self) -> bool {
+        self.panic_on_close
+    }
+}
+impl Default for OutputPortOptions {
+    fn default() -> Self {
+        OutputPortOptions::new(1024)
+    }
+}
+impl fmt::Debug for OutputPortOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OutputPortOptions")
+            .field("buffer_size", &self.buffer_size)
+            .field("latency", &self.latency)
+            .field("time_source", &self.time_source.is_some())
+            .field("retry_policy", &self.retry_policy)
+            .field("panic_on_close", &self.panic_on_close)
+            .finish()
+    }
 }
-unsafe impl<'a> Send for InputPort<'a> {}
 
 /// Represents the output port of a PortMidi device.
-pub struct OutputPort<'a> {
-    stream: *const ffi::PortMidiStream,
-    _context: &'a PortMidi, // Used for lifetime pinning
+pub struct OutputPort {
+    stream: Box<dyn Stream>,
+    // keeps the context initialized while the port is open
+    _context: PortMidi,
     device: DeviceInfo,
+    buffer_size: usize,
+    latency: u32,
+    time_source: Option<Arc<dyn TimeSource>>,
+    retry_policy: RetryPolicy,
+    // the events collected by `write_iter`, reused across calls
+    events: Vec<MidiEvent>,
+    panic_on_close: bool,
+    closed: bool,
 }
-impl<'a> OutputPort<'a> {
+impl OutputPort {
     /// Construct a new `OutputPort` for the given device and buffer size.
+    /// The port is opened without latency, so timestamps of written events are ignored.
     ///
     /// If the `device` is not an output device an `Error::NotAnOutputDevice` is returned.
-    pub fn new(
-        context: &'a PortMidi,
+    pub fn new(context: &PortMidi, device: DeviceInfo, buffer_size: usize) -> Result<OutputPort> {
+        OutputPort::with_options(context, device, OutputPortOptions::new(buffer_size))
+    }
+
+    /// Construct a new `OutputPort` for the given device and `OutputPortOptions`.
+    ///
+    /// If the `device` is not an output device an `Error::NotAnOutputDevice` is returned.
+    pub fn with_options(
+        context: &PortMidi,
         device: DeviceInfo,
-        buffer_size: usize,
+        options: OutputPortOptions,
     ) -> Result<OutputPort> {
         if device.is_input() {
             return Err(Error::NotAnOutputDevice);
         }
-        let raw_stream: *const ffi::PortMidiStream = ptr::null();
-        Result::from(unsafe {
-            ffi::Pm_OpenOutput(
-                &raw_stream as *const *const _,
-                device.id(),
-                ptr::null(), // *inputDriverInfo, not needed for normal operation
-                buffer_size as c_int,
-                ptr::null(), // PmTimeProcPtr, a procedure that returns time in ms,
-                ptr::null(), // time_info, a pointer passed to the time procedure
-                0,
-            ) //latency
-        })?;
+        let stream = context.open_output(device.id(), &options)?;
 
         Ok(OutputPort {
-            stream: raw_stream,
-            _context: context,
+            stream,
+            _context: context.clone(),
             device,
+            buffer_size: options.buffer_size,
+            latency: options.latency,
+            time_source: options.time_source,
+            retry_policy: options.retry_policy,
+            events: Vec::new(),
+            panic_on_close: options.panic_on_close,
+            closed: false,
         })
     }
 
     /// Write a single `MidiEvent`.
     /// Returns an `Error::PortMidi(_)` if something went wrong.
     pub fn write_event<T: Into<MidiEvent>>(&mut self, midi_event: T) -> Result<()> {
-        self.write_events(vec![midi_event])
+        self.write_slice(&[midi_event.into()])
     }
 
     /// Write a buffer of midi events to the output port.
+    /// The timestamps of the events are passed unchanged to PortMidi, which
+    /// schedules them if the port was opened with a latency.
     /// Returns an `Error::PortMidi(_)` if something went wrong.
     pub fn write_events<T: Into<MidiEvent>>(&mut self, midi_events: Vec<T>) -> Result<()> {
-        let events: Vec<ffi::PmEvent> = midi_events
-            .into_iter()
-            .map(|event| event.into().into())
-            .collect();
-        Result::from(unsafe { ffi::Pm_Write(self.stream, events.as_ptr(), events.len() as c_int) })
+        self.write_iter(midi_events)
+    }
+
+    /// Writes the events in chunks of at most `buffer_size` events without allocating,
+    /// an overflow of the output buffer is retried according to the `RetryPolicy`.
+    /// If a chunk fails, the following chunks are not written.
+    /// Returns an `Error::PortMidi(_)` if something went wrong.
+    pub fn write_slice(&mut self, midi_events: &[MidiEvent]) -> Result<()> {
+        for chunk in midi_events.chunks(self.buffer_size.max(1)) {
+            write_chunk(&*self.stream, self.retry_policy, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Like `write_slice`, but collects the chunks from an iterator into storage owned
+    /// by the port, which is reused, so only the first call allocates.
+    ///
+    /// ```no_run
+    /// # use portmidi::{MidiMessage, PortMidi};
+    /// let context = PortMidi::new().unwrap();
+    /// let mut out_port = context.default_output_port(1024).unwrap();
+    /// let notes = (60..72).map(|note| MidiMessage::from([0x90, note, 100, 0]));
+    /// out_port.write_iter(notes).unwrap();
+    /// ```
+    pub fn write_iter<I>(&mut self, midi_events: I) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: Into<MidiEvent>,
+    {
+        let chunk_size = self.buffer_size.max(1);
+        self.events.clear();
+        for midi_event in midi_events {
+            self.events.push(midi_event.into());
+            if self.events.len() == chunk_size {
+                let result = write_chunk(&*self.stream, self.retry_policy, &self.events);
+                self.events.clear();
+                result?;
+            }
+        }
+        let result = if self.events.is_empty() {
+            Ok(())
+        } else {
+            write_chunk(&*self.stream, self.retry_policy, &self.events)
+        };
+        self.events.clear();
+        result
+    }
+
+    /// Sets how writes that overflow the output buffer are retried.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Returns the `RetryPolicy` for writes that overflow the output buffer.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    /// Returns the number of events that can be buffered by the port.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Sends All Notes Off and Reset All Controllers on every channel, to silence
+    /// notes that would otherwise hang, e.g. when playback is stopped.
+    /// Returns an `Error::PortMidi(_)` if something went wrong.
+    pub fn send_panic(&mut self) -> Result<()> {
+        for channel in 0..16 {
+            for &controller in &[CONTROL_ALL_NOTES_OFF, CONTROL_RESET_ALL_CONTROLLERS] {
+                self.write_message([0xB0 | channel, controller, 0, 0])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets whether the panic messages are sent when the port is closed.
+    pub fn set_panic_on_close(&mut self, panic_on_close: bool) {
+        self.panic_on_close = panic_on_close;
+    }
+
+    /// Returns `true` if the panic messages are sent when the port is closed.
+    pub fn panic_on_close(&self) -> bool {
+        self.panic_on_close
+    }
+
+    /// Closes the port, after sending the panic messages if `panic_on_close` is set.
+    /// Unlike dropping the port, which ignores errors, this returns them.
+    /// The port is closed even if sending the panic messages fails.
+    pub fn close(mut self) -> Result<()> {
+        self.close_stream()
+    }
+
+    /// Discards the events that are not sent yet, e.g. events scheduled with a latency,
+    /// and closes the port. The panic messages are not sent, as they would be
+    /// discarded as well.
+    pub fn abort(mut self) -> Result<()> {
+        self.closed = true;
+        let aborted = self.stream.abort();
+        let closed = self.stream.close();
+        aborted.and(closed)
+    }
+
+    fn close_stream(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let sent = if self.panic_on_close {
+            self.send_panic()
+        } else {
+            Ok(())
+        };
+        let closed = self.stream.close();
+        sent.and(closed)
     }
 
     /// Write a single `MidiMessage`.
+    /// Typed messages like `ChannelMessage`, `SystemMessage` or `Message` are accepted as well.
     /// Returns an `Error::PortMidi(_)` if something went wrong.
     pub fn write_message<T: Into<MidiMessage>>(&mut self, midi_message: T) -> Result<()> {
-        Result::from(unsafe { ffi::Pm_WriteShort(self.stream, 0, midi_message.into().into()) })
+        self.stream.write_short(0, midi_message.into())
+    }
+
+    /// Returns `true` if the host MIDI API has a pending error for this port, e.g. after
+    /// the device was disconnected. The next call that fails returns it as `Error::Host`.
+    pub fn has_host_error(&self) -> bool {
+        self.stream.has_host_error()
     }
 
     /// Returns the `DeviceInfo` of the Midi device that owns this port.
@@ -171,21 +610,71 @@ impl<'a> OutputPort<'a> {
         self.device.clone()
     }
 
+    /// Returns the output latency in milliseconds the port was opened with.
+    pub fn latency(&self) -> u32 {
+        self.latency
+    }
+
+    /// Returns the current time of the clock that timestamps refer to, the port's
+    /// `TimeSource` or PortTime.
+    pub fn time(&self) -> ffi::PmTimestamp {
+        match self.time_source {
+            Some(ref time_source) => time_source.now(),
+            None => porttime::time(),
+        }
+    }
+
     // Write arbitrarily long EOX-terminated data
     pub fn write_sysex(&self, timestamp: ffi::PmTimestamp, msg: &[u8]) -> Result<()> {
         // Sysex writes MUST be EOX-terminated
         if Some(&ffi::MIDI_EOX) != msg.last() {
             Err(Error::Invalid)
         } else {
-            Result::from(unsafe { ffi::Pm_WriteSysEx(self.stream, timestamp, msg.as_ptr()) })
+            self.stream.write_sysex(timestamp, msg)
         }
     }
 }
-impl<'a> Drop for OutputPort<'a> {
+impl Drop for OutputPort {
     fn drop(&mut self) {
-        if let Err(err) = Result::from(unsafe { ffi::Pm_Close(self.stream) }) {
-            println!("{}", err);
+        // use `close` to handle errors
+        let _ = self.close_stream();
+    }
+}
+
+/// Writes `events` to `stream`, retrying an overflow according to `retry_policy`.
+fn write_chunk(stream: &dyn Stream, retry_policy: RetryPolicy, events: &[MidiEvent]) -> Result<()> {
+    if retry_policy.max_retries() == 0 {
+        return stream.write(events);
+    }
+    // the events before an overflow are sent, so only the failed event is retried
+    for event in events {
+        let mut retry = 0;
+        loop {
+            match stream.write(slice::from_ref(event)) {
+                Err(Error::PortMidi(ffi::PmError::PmBufferOverflow))
+                    if retry < retry_policy.max_retries() =>
+                {
+                    thread::sleep(retry_policy.backoff(retry));
+                    retry += 1;
+                }
+                result => {
+                    result?;
+                    break;
+                }
+            }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputPortOptions;
+
+    #[test]
+    fn latency_is_clamped_to_i32() {
+        assert_eq!(OutputPortOptions::new(16).with_latency(20).latency(), 20);
+        let options = OutputPortOptions::new(16).with_latency(u32::MAX);
+        assert_eq!(options.latency(), i32::MAX as u32);
+    }
 }
-unsafe impl<'a> Send for OutputPort<'a> {}
@@ -5,19 +5,53 @@
 //          Apache License, Version 2.0, (LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0)
 //          MIT license (LICENSE-MIT or http://opensource.org/licenses/MIT).
 // This file may not be copied, modified, or distributed except according to those terms.
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "dynamic")]
+extern crate libloading;
+
+pub mod backend;
 mod device;
 mod ffi;
 mod vdevice;
 pub use device::*;
-pub use vdevice::VirtualDevice;
+pub use vdevice::{VirtualDevice, VirtualDeviceOptions};
+mod duplex;
+pub use duplex::{DevicePair, DuplexPort};
+mod filter;
+pub use filter::{ChannelMask, InputFilter};
 mod io;
 pub use io::*;
+#[cfg(feature = "dynamic")]
+pub mod library;
+mod listener;
+pub use listener::{InputListener, DEFAULT_POLL_INTERVAL};
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use stream::MidiEventStream;
 
-pub use ffi::PmError;
+pub use ffi::{PmError, PmTimestamp, PtError};
+mod message;
+pub use message::*;
+mod player;
+pub use player::{PlayState, Playback, Player, PlayerControl};
+pub mod porttime;
+mod query;
+pub use query::DeviceQuery;
+mod recorder;
+pub use recorder::{RecordFormat, Recorder};
+pub mod smf;
+mod sysex;
+pub use sysex::*;
 pub mod types;
 pub use types::*;
 mod context;
 pub use context::*;
+mod time;
+pub use time::TimeSource;
+mod watcher;
+pub use watcher::{DeviceEvent, DeviceWatcher, DEFAULT_WATCH_INTERVAL};
 
 pub const HDRLENGTH: i32 = 50;
 pub const PM_HOST_ERROR_MSG_LEN: i32 = 256;
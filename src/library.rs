@@ -0,0 +1,167 @@
+//! Loads the PortMidi shared library at runtime, enabled by the `dynamic` feature.
+//!
+//! The library is loaded by `PortMidi::new`, `DeviceInfo::new` and the `porttime` entry
+//! points, which return `Error::LibraryNotFound` or `Error::MissingSymbol` if it is
+//! unavailable, e.g. a PortMidi 1.x library lacks `Pm_CreateVirtualInput`. The candidates are tried in this order:
+//!
+//! * the path in the `PORTMIDI_LIBRARY` environment variable,
+//! * the paths given to `set_search_paths`, a directory is searched for the default names,
+//! * the default names (e.g. `libportmidi.so.2`), found by the platform's loader.
+use ffi::Api;
+use libloading::Library;
+use std::env;
+use std::error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+const DEFAULT_NAMES: &[&str] = &["libportmidi.so.2", "libportmidi.so", "libportmidi.so.0"];
+#[cfg(target_os = "macos")]
+const DEFAULT_NAMES: &[&str] = &["libportmidi.2.dylib", "libportmidi.dylib"];
+#[cfg(windows)]
+const DEFAULT_NAMES: &[&str] = &["portmidi.dll"];
+
+/// The loaded library, never unloaded once set.
+static API: AtomicPtr<Loaded> = AtomicPtr::new(ptr::null_mut());
+/// Serializes loading and holds the paths given to `set_search_paths`.
+static SEARCH_PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+struct Loaded {
+    api: Api,
+    // keeps the functions in `api` valid
+    _library: Library,
+}
+
+/// The reason the PortMidi library could not be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// None of the candidates could be loaded, the message lists the attempts.
+    NotFound(String),
+    /// The library lacks a function, e.g. it is older than PortMidi 2.0.
+    MissingSymbol(&'static str),
+}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::NotFound(ref message) => {
+                write!(f, "could not load the PortMidi library: {}", message)
+            }
+            LoadError::MissingSymbol(symbol) => write!(
+                f,
+                "the PortMidi library lacks `{}`, PortMidi 2.0 or newer is required",
+                symbol
+            ),
+        }
+    }
+}
+impl error::Error for LoadError {}
+
+/// Sets the files or directories that are searched for the library.
+/// Only has an effect before the library is loaded.
+pub fn set_search_paths<I, P>(paths: I)
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    *SEARCH_PATHS.lock().unwrap() = paths.into_iter().map(Into::into).collect();
+}
+
+/// Returns `true` if the library has been loaded.
+pub fn is_loaded() -> bool {
+    !API.load(Ordering::Acquire).is_null()
+}
+
+/// Loads the library if it isn't loaded yet.
+pub fn load() -> Result<(), LoadError> {
+    if is_loaded() {
+        return Ok(());
+    }
+    let paths = SEARCH_PATHS.lock().unwrap();
+    if is_loaded() {
+        return Ok(());
+    }
+    let loaded = Box::new(open(&candidates(&paths))?);
+    API.store(Box::into_raw(loaded), Ordering::Release);
+    Ok(())
+}
+
+/// Opens the first candidate that can be loaded.
+fn open(candidates: &[PathBuf]) -> Result<Loaded, LoadError> {
+    let mut attempts = Vec::new();
+    for candidate in candidates {
+        let library = match unsafe { Library::new(candidate) } {
+            Ok(library) => library,
+            Err(err) => {
+                attempts.push(format!("{}: {}", candidate.display(), err));
+                continue;
+            }
+        };
+        let api = unsafe { Api::load(&library) }.map_err(LoadError::MissingSymbol)?;
+        return Ok(Loaded {
+            api,
+            _library: library,
+        });
+    }
+    Err(LoadError::NotFound(attempts.join("; ")))
+}
+
+/// Returns the loaded functions, loading the library on first use.
+///
+/// # Panics
+///
+/// Panics if the library can't be loaded, the public entry points call `load` first
+/// and report this as an error instead.
+pub(crate) fn api() -> &'static Api {
+    let mut loaded = API.load(Ordering::Acquire);
+    if loaded.is_null() {
+        if let Err(err) = load() {
+            panic!("{}", err);
+        }
+        loaded = API.load(Ordering::Acquire);
+    }
+    unsafe { &(*loaded).api }
+}
+
+fn candidates(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(path) = env::var_os("PORTMIDI_LIBRARY") {
+        candidates.push(PathBuf::from(path));
+    }
+    for path in paths {
+        if path.is_dir() {
+            candidates.extend(DEFAULT_NAMES.iter().map(|name| path.join(name)));
+        } else {
+            candidates.push(path.clone());
+        }
+    }
+    candidates.extend(
+        DEFAULT_NAMES
+            .iter()
+            .map(|name| Path::new(name).to_path_buf()),
+    );
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open, LoadError};
+    use std::path::PathBuf;
+
+    #[test]
+    fn missing_candidates_are_listed() {
+        let candidates = [
+            PathBuf::from("/nonexistent/libportmidi.so"),
+            PathBuf::from("/nonexistent/portmidi.dll"),
+        ];
+        match open(&candidates) {
+            Err(LoadError::NotFound(attempts)) => {
+                assert!(attempts.starts_with("/nonexistent/libportmidi.so: "));
+                assert!(attempts.contains("; /nonexistent/portmidi.dll: "));
+            }
+            _ => panic!("loaded a nonexistent library"),
+        }
+    }
+}
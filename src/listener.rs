@@ -0,0 +1,100 @@
+use io::InputPort;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use types::*;
+
+/// The poll interval used by `InputPort::listen`.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl InputPort {
+    /// Moves the port into a background thread that calls `callback` for every received
+    /// `MidiEvent`, polling the port every `DEFAULT_POLL_INTERVAL`.
+    ///
+    /// The returned `InputListener` stops the thread on drop, use `InputListener::stop`
+    /// to get the port back.
+    pub fn listen<F>(self, callback: F) -> InputListener
+    where
+        F: FnMut(MidiEvent) + Send + 'static,
+    {
+        self.listen_with_interval(DEFAULT_POLL_INTERVAL, callback)
+    }
+
+    /// Like `listen`, but polls the port every `poll_interval`.
+    /// Available events are drained without sleeping, so the interval only
+    /// bounds the latency of the first event after an idle period.
+    pub fn listen_with_interval<F>(self, poll_interval: Duration, mut callback: F) -> InputListener
+    where
+        F: FnMut(MidiEvent) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let error = Arc::new(Mutex::new(None));
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            let error = error.clone();
+            move || {
+                let buffer_size = self.buffer_size();
+                while !stop.load(Ordering::SeqCst) {
+                    match self.read_n(buffer_size) {
+                        Ok(Some(ref events)) if !events.is_empty() => {
+                            events.iter().for_each(|&event| callback(event));
+                        }
+                        Ok(_) => thread::sleep(poll_interval),
+                        Err(err) => {
+                            *error.lock().unwrap() = Some(err);
+                            break;
+                        }
+                    }
+                }
+                self
+            }
+        });
+        InputListener {
+            stop,
+            error,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// A handle to the background thread started by `InputPort::listen`.
+///
+/// The thread is stopped and joined when the handle is dropped.
+pub struct InputListener {
+    stop: Arc<AtomicBool>,
+    error: Arc<Mutex<Option<Error>>>,
+    thread: Option<thread::JoinHandle<InputPort>>,
+}
+impl InputListener {
+    /// Returns `false` if the listener thread has stopped because reading failed.
+    /// The error can be obtained with `error`.
+    pub fn is_running(&self) -> bool {
+        !self.stop.load(Ordering::SeqCst) && self.error().is_none()
+    }
+
+    /// Returns the error that stopped the listener thread, if any.
+    pub fn error(&self) -> Option<Error> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// Stops and joins the listener thread and returns the port.
+    ///
+    /// If the callback panicked, the panic is propagated to the caller.
+    pub fn stop(mut self) -> InputPort {
+        self.stop.store(true, Ordering::SeqCst);
+        let thread = self.thread.take().expect("listener thread already joined");
+        match thread.join() {
+            Ok(port) => port,
+            Err(panic) => ::std::panic::resume_unwind(panic),
+        }
+    }
+}
+impl Drop for InputListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use ffi;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use types::{MidiEvent, MidiMessage};
+
+/// Error returned when raw bytes can't be decoded into a typed message or when
+/// a typed message is built from out of range values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageError {
+    /// The status byte is not a valid status byte for a typed message,
+    /// e.g. a data byte (`< 0x80`), SysEx start/end or an undefined system status.
+    InvalidStatus(u8),
+    /// The channel is not in the range `0..=15`.
+    ChannelOutOfRange(u8),
+    /// A data byte is not in the range `0..=127`.
+    DataOutOfRange(u8),
+    /// A 14 bit value (pitch bend, song position) is not in the range `0..=0x3FFF`.
+    ValueOutOfRange(u16),
+    /// A data byte that is not used by the message is not zero.
+    UnexpectedData(u8),
+}
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MessageError::InvalidStatus(status) => {
+                write!(f, "invalid status byte: {:#04x}", status)
+            }
+            MessageError::ChannelOutOfRange(channel) => {
+                write!(f, "channel out of range: {}", channel)
+            }
+            MessageError::DataOutOfRange(data) => write!(f, "data byte out of range: {}", data),
+            MessageError::ValueOutOfRange(value) => write!(f, "value out of range: {}", value),
+            MessageError::UnexpectedData(data) => write!(f, "unexpected data byte: {}", data),
+        }
+    }
+}
+impl error::Error for MessageError {
+    fn description(&self) -> &str {
+        match *self {
+            MessageError::InvalidStatus(_) => "portmidi-rs: Invalid status byte",
+            MessageError::ChannelOutOfRange(_) => "portmidi-rs: Channel out of range",
+            MessageError::DataOutOfRange(_) => "portmidi-rs: Data byte out of range",
+            MessageError::ValueOutOfRange(_) => "portmidi-rs: Value out of range",
+            MessageError::UnexpectedData(_) => "portmidi-rs: Unexpected data byte",
+        }
+    }
+}
+
+fn check_channel(channel: u8) -> Result<u8, MessageError> {
+    if channel <= 0x0F {
+        Ok(channel)
+    } else {
+        Err(MessageError::ChannelOutOfRange(channel))
+    }
+}
+
+fn check_data(data: u8) -> Result<u8, MessageError> {
+    if data <= 0x7F {
+        Ok(data)
+    } else {
+        Err(MessageError::DataOutOfRange(data))
+    }
+}
+
+fn check_value(value: u16) -> Result<u16, MessageError> {
+    if value <= 0x3FFF {
+        Ok(value)
+    } else {
+        Err(MessageError::ValueOutOfRange(value))
+    }
+}
+
+fn check_unused(data: u8) -> Result<(), MessageError> {
+    if data == 0 {
+        Ok(())
+    } else {
+        Err(MessageError::UnexpectedData(data))
+    }
+}
+
+fn join_14bit(lsb: u8, msb: u8) -> u16 {
+    (msb as u16) << 7 | lsb as u16
+}
+
+fn split_14bit(value: u16) -> (u8, u8) {
+    ((value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8)
+}
+
+/// A typed MIDI channel voice message.
+///
+/// Channels are zero based (`0..=15`), data values are 7 bit (`0..=127`) and
+/// pitch bend values are 14 bit (`0..=0x3FFF`, `0x2000` is the center).
+/// Use the constructor functions to get validated messages, out of range values
+/// in messages built by hand are masked when converting to a `MidiMessage`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChannelMessage {
+    NoteOff {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        key: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    Aftertouch {
+        channel: u8,
+        pressure: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+}
+impl ChannelMessage {
+    /// Creates a validated `NoteOff` message.
+    pub fn note_off(channel: u8, key: u8, velocity: u8) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::NoteOff {
+            channel: check_channel(channel)?,
+            key: check_data(key)?,
+            velocity: check_data(velocity)?,
+        })
+    }
+
+    /// Creates a validated `NoteOn` message.
+    pub fn note_on(channel: u8, key: u8, velocity: u8) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::NoteOn {
+            channel: check_channel(channel)?,
+            key: check_data(key)?,
+            velocity: check_data(velocity)?,
+        })
+    }
+
+    /// Creates a validated `PolyPressure` message.
+    pub fn poly_pressure(channel: u8, key: u8, pressure: u8) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::PolyPressure {
+            channel: check_channel(channel)?,
+            key: check_data(key)?,
+            pressure: check_data(pressure)?,
+        })
+    }
+
+    /// Creates a validated `ControlChange` message.
+    pub fn control_change(channel: u8, controller: u8, value: u8) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::ControlChange {
+            channel: check_channel(channel)?,
+            controller: check_data(controller)?,
+            value: check_data(value)?,
+        })
+    }
+
+    /// Creates a validated `ProgramChange` message.
+    pub fn program_change(channel: u8, program: u8) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::ProgramChange {
+            channel: check_channel(channel)?,
+            program: check_data(program)?,
+        })
+    }
+
+    /// Creates a validated `Aftertouch` (channel pressure) message.
+    pub fn aftertouch(channel: u8, pressure: u8) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::Aftertouch {
+            channel: check_channel(channel)?,
+            pressure: check_data(pressure)?,
+        })
+    }
+
+    /// Creates a validated `PitchBend` message.
+    pub fn pitch_bend(channel: u8, value: u16) -> Result<Self, MessageError> {
+        Ok(ChannelMessage::PitchBend {
+            channel: check_channel(channel)?,
+            value: check_value(value)?,
+        })
+    }
+
+    /// Returns the zero based channel of the message.
+    pub fn channel(&self) -> u8 {
+        match *self {
+            ChannelMessage::NoteOff { channel, .. }
+            | ChannelMessage::NoteOn { channel, .. }
+            | ChannelMessage::PolyPressure { channel, .. }
+            | ChannelMessage::ControlChange { channel, .. }
+            | ChannelMessage::ProgramChange { channel, .. }
+            | ChannelMessage::Aftertouch { channel, .. }
+            | ChannelMessage::PitchBend { channel, .. } => channel,
+        }
+    }
+}
+impl TryFrom<MidiMessage> for ChannelMessage {
+    type Error = MessageError;
+
+    fn try_from(msg: MidiMessage) -> Result<Self, MessageError> {
+        let channel = msg.status & 0x0F;
+        let message = match msg.status & 0xF0 {
+            0x80 => ChannelMessage::note_off(channel, msg.data1, msg.data2),
+            0x90 => ChannelMessage::note_on(channel, msg.data1, msg.data2),
+            0xA0 => ChannelMessage::poly_pressure(channel, msg.data1, msg.data2),
+            0xB0 => ChannelMessage::control_change(channel, msg.data1, msg.data2),
+            0xC0 => {
+                check_unused(msg.data2)?;
+                ChannelMessage::program_change(channel, msg.data1)
+            }
+            0xD0 => {
+                check_unused(msg.data2)?;
+                ChannelMessage::aftertouch(channel, msg.data1)
+            }
+            0xE0 => {
+                let lsb = check_data(msg.data1)?;
+                let msb = check_data(msg.data2)?;
+                ChannelMessage::pitch_bend(channel, join_14bit(lsb, msb))
+            }
+            _ => Err(MessageError::InvalidStatus(msg.status)),
+        }?;
+        check_unused(msg.data3)?;
+        Ok(message)
+    }
+}
+impl From<ChannelMessage> for MidiMessage {
+    fn from(msg: ChannelMessage) -> Self {
+        let (status, data1, data2) = match msg {
+            ChannelMessage::NoteOff { key, velocity, .. } => (0x80, key, velocity),
+            ChannelMessage::NoteOn { key, velocity, .. } => (0x90, key, velocity),
+            ChannelMessage::PolyPressure { key, pressure, .. } => (0xA0, key, pressure),
+            ChannelMessage::ControlChange {
+                controller, value, ..
+            } => (0xB0, controller, value),
+            ChannelMessage::ProgramChange { program, .. } => (0xC0, program, 0),
+            ChannelMessage::Aftertouch { pressure, .. } => (0xD0, pressure, 0),
+            ChannelMessage::PitchBend { value, .. } => {
+                let (lsb, msb) = split_14bit(value);
+                (0xE0, lsb, msb)
+            }
+        };
+        MidiMessage {
+            status: status | (msg.channel() & 0x0F),
+            data1: data1 & 0x7F,
+            data2: data2 & 0x7F,
+            data3: 0,
+        }
+    }
+}
+
+/// A typed MIDI system common or system realtime message.
+///
+/// System exclusive messages don't fit into a single `MidiMessage` and are
+/// therefore not represented here.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SystemMessage {
+    TimeCodeQuarterFrame(u8),
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+}
+impl TryFrom<MidiMessage> for SystemMessage {
+    type Error = MessageError;
+
+    fn try_from(msg: MidiMessage) -> Result<Self, MessageError> {
+        let (message, data1, data2) = match msg.status {
+            0xF1 => (
+                SystemMessage::TimeCodeQuarterFrame(check_data(msg.data1)?),
+                0,
+                msg.data2,
+            ),
+            0xF2 => {
+                let lsb = check_data(msg.data1)?;
+                let msb = check_data(msg.data2)?;
+                (SystemMessage::SongPosition(join_14bit(lsb, msb)), 0, 0)
+            }
+            0xF3 => (
+                SystemMessage::SongSelect(check_data(msg.data1)?),
+                0,
+                msg.data2,
+            ),
+            0xF6 => (SystemMessage::TuneRequest, msg.data1, msg.data2),
+            0xF8 => (SystemMessage::Clock, msg.data1, msg.data2),
+            0xFA => (SystemMessage::Start, msg.data1, msg.data2),
+            0xFB => (SystemMessage::Continue, msg.data1, msg.data2),
+            0xFC => (SystemMessage::Stop, msg.data1, msg.data2),
+            0xFE => (SystemMessage::ActiveSensing, msg.data1, msg.data2),
+            0xFF => (SystemMessage::Reset, msg.data1, msg.data2),
+            status => return Err(MessageError::InvalidStatus(status)),
+        };
+        check_unused(data1)?;
+        check_unused(data2)?;
+        check_unused(msg.data3)?;
+        Ok(message)
+    }
+}
+impl From<SystemMessage> for MidiMessage {
+    fn from(msg: SystemMessage) -> Self {
+        let (status, data1, data2) = match msg {
+            SystemMessage::TimeCodeQuarterFrame(data) => (0xF1, data & 0x7F, 0),
+            SystemMessage::SongPosition(value) => {
+                let (lsb, msb) = split_14bit(value);
+                (0xF2, lsb, msb)
+            }
+            SystemMessage::SongSelect(song) => (0xF3, song & 0x7F, 0),
+            SystemMessage::TuneRequest => (0xF6, 0, 0),
+            SystemMessage::Clock => (0xF8, 0, 0),
+            SystemMessage::Start => (0xFA, 0, 0),
+            SystemMessage::Continue => (0xFB, 0, 0),
+            SystemMessage::Stop => (0xFC, 0, 0),
+            SystemMessage::ActiveSensing => (0xFE, 0, 0),
+            SystemMessage::Reset => (0xFF, 0, 0),
+        };
+        MidiMessage {
+            status,
+            data1,
+            data2,
+            data3: 0,
+        }
+    }
+}
+
+/// A typed MIDI message, either a channel or a system message.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Message {
+    Channel(ChannelMessage),
+    System(SystemMessage),
+}
+impl TryFrom<MidiMessage> for Message {
+    type Error = MessageError;
+
+    fn try_from(msg: MidiMessage) -> Result<Self, MessageError> {
+        match msg.status {
+            0x80..=0xEF => ChannelMessage::try_from(msg).map(Message::Channel),
+            _ => SystemMessage::try_from(msg).map(Message::System),
+        }
+    }
+}
+impl From<Message> for MidiMessage {
+    fn from(msg: Message) -> Self {
+        match msg {
+            Message::Channel(msg) => MidiMessage::from(msg),
+            Message::System(msg) => MidiMessage::from(msg),
+        }
+    }
+}
+impl From<ChannelMessage> for Message {
+    fn from(msg: ChannelMessage) -> Self {
+        Message::Channel(msg)
+    }
+}
+impl From<SystemMessage> for Message {
+    fn from(msg: SystemMessage) -> Self {
+        Message::System(msg)
+    }
+}
+
+/// Represents a time stamped typed midi message. See also `MidiEvent`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MessageEvent {
+    pub message: Message,
+    pub timestamp: ffi::PmTimestamp,
+}
+impl TryFrom<MidiEvent> for MessageEvent {
+    type Error = MessageError;
+
+    fn try_from(event: MidiEvent) -> Result<Self, MessageError> {
+        Ok(MessageEvent {
+            message: Message::try_from(event.message)?,
+            timestamp: event.timestamp,
+        })
+    }
+}
+impl From<MessageEvent> for MidiEvent {
+    fn from(event: MessageEvent) -> Self {
+        MidiEvent {
+            message: MidiMessage::from(event.message),
+            timestamp: event.timestamp,
+        }
+    }
+}
+impl From<Message> for MidiEvent {
+    fn from(msg: Message) -> Self {
+        MidiEvent::from(MidiMessage::from(msg))
+    }
+}
+impl From<ChannelMessage> for MidiEvent {
+    fn from(msg: ChannelMessage) -> Self {
+        MidiEvent::from(MidiMessage::from(msg))
+    }
+}
+impl From<SystemMessage> for MidiEvent {
+    fn from(msg: SystemMessage) -> Self {
+        MidiEvent::from(MidiMessage::from(msg))
+    }
+}
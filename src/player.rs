@@ -0,0 +1,479 @@
+use ffi;
+use io::{OutputPort, CONTROL_ALL_NOTES_OFF};
+use smf::{EventKind, Format, MetaEvent, Smf, Timing, Track};
+use std::ops::Range;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use types::*;
+
+/// The default tempo of a file without tempo events, 120 quarter notes per minute.
+const DEFAULT_TEMPO: u64 = 500_000;
+
+const CONTROL_SUSTAIN: u8 = 64;
+
+/// The playback state of a `Player`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayState {
+    Playing,
+    Paused,
+    /// Playback was stopped or reached the end of the file.
+    Stopped,
+}
+
+#[derive(Clone, Debug)]
+enum PlayEvent {
+    Midi(MidiMessage),
+    SysEx(Vec<u8>),
+}
+
+/// An event with its position in microseconds from the start of the file.
+#[derive(Clone, Debug)]
+struct TimedEvent {
+    time: u64,
+    event: PlayEvent,
+}
+
+/// Converts the tracks of a file into a single list of events ordered by time,
+/// applying the tempo map.
+/// Tracks of format 2 files are independent and are played one after the other.
+fn timeline(smf: &Smf) -> (Vec<TimedEvent>, u64) {
+    let mut events = Vec::new();
+    let mut end = 0;
+    if smf.format == Format::MultiSong {
+        for track in &smf.tracks {
+            end = merge(&[track], smf.timing, end, &mut events);
+        }
+    } else {
+        let tracks: Vec<&Track> = smf.tracks.iter().collect();
+        end = merge(&tracks, smf.timing, 0, &mut events);
+    }
+    (events, end)
+}
+
+/// Appends the events of the merged tracks starting at `offset` microseconds
+/// and returns the time of the end of the last track.
+fn merge(tracks: &[&Track], timing: Timing, offset: u64, output: &mut Vec<TimedEvent>) -> u64 {
+    let mut events = Vec::new();
+    for track in tracks {
+        let mut ticks = 0u64;
+        for event in &track.events {
+            ticks += u64::from(event.delta);
+            events.push((ticks, &event.kind));
+        }
+    }
+    // the sort is stable, so simultaneous events keep the order of the tracks
+    events.sort_by_key(|&(ticks, _)| ticks);
+
+    let (mut base_ticks, mut base_time, mut tempo) = (0u64, offset, DEFAULT_TEMPO);
+    let to_time = |ticks: u64, base_ticks: u64, base_time: u64, tempo: u64| match timing {
+        Timing::Metrical(ppq) => base_time + (ticks - base_ticks) * tempo / u64::from(ppq.max(1)),
+        Timing::Timecode {
+            fps,
+            ticks_per_frame,
+        } => {
+            // 29 stands for 30 drop frame, i.e. 29.97 frames per second
+            let centi_fps = if fps == 29 {
+                2997
+            } else {
+                u64::from(fps) * 100
+            };
+            let ticks_per_second = (centi_fps * u64::from(ticks_per_frame)).max(1);
+            base_time + (ticks - base_ticks) * 100_000_000 / ticks_per_second
+        }
+    };
+    let mut end = offset;
+    for (ticks, kind) in events {
+        let time = to_time(ticks, base_ticks, base_time, tempo);
+        end = end.max(time);
+        let event = match *kind {
+            EventKind::Midi(msg) => PlayEvent::Midi(msg),
+            EventKind::SysEx(ref data) => PlayEvent::SysEx(data.clone()),
+            EventKind::Meta(MetaEvent::Tempo(new_tempo)) => {
+                base_ticks = ticks;
+                base_time = time;
+                tempo = u64::from(new_tempo);
+                continue;
+            }
+            _ => continue,
+        };
+        output.push(TimedEvent { time, event });
+    }
+    end
+}
+
+fn to_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+#[derive(Debug)]
+struct Transport {
+    state: PlayState,
+    // the song position at `anchor_instant`
+    anchor_position: u64,
+    anchor_instant: Instant,
+    seek: bool,
+    loop_region: Option<Range<u64>>,
+}
+impl Transport {
+    fn position(&self) -> u64 {
+        match self.state {
+            PlayState::Playing => self.anchor_position + to_micros(self.anchor_instant.elapsed()),
+            _ => self.anchor_position,
+        }
+    }
+
+    fn jump(&mut self, position: u64) {
+        self.anchor_position = position;
+        self.anchor_instant = Instant::now();
+        self.seek = true;
+    }
+}
+
+/// A handle to control a `Player` from another thread.
+#[derive(Clone, Debug)]
+pub struct PlayerControl {
+    shared: Arc<(Mutex<Transport>, Condvar)>,
+    duration: u64,
+}
+impl PlayerControl {
+    fn lock(&self) -> MutexGuard<'_, Transport> {
+        self.shared.0.lock().unwrap()
+    }
+
+    fn update<F: FnOnce(&mut Transport)>(&self, f: F) {
+        f(&mut self.lock());
+        self.shared.1.notify_all();
+    }
+
+    /// Returns the playback state.
+    pub fn state(&self) -> PlayState {
+        self.lock().state
+    }
+
+    /// Returns the current position in the file.
+    pub fn position(&self) -> Duration {
+        Duration::from_micros(self.lock().position().min(self.duration))
+    }
+
+    /// Pauses playback, all sounding notes are turned off.
+    pub fn pause(&self) {
+        self.update(|transport| {
+            if transport.state == PlayState::Playing {
+                transport.anchor_position = transport.position();
+                transport.state = PlayState::Paused;
+            }
+        })
+    }
+
+    /// Resumes paused playback.
+    pub fn resume(&self) {
+        self.update(|transport| {
+            if transport.state == PlayState::Paused {
+                transport.anchor_instant = Instant::now();
+                transport.state = PlayState::Playing;
+            }
+        })
+    }
+
+    /// Moves the playback position, all sounding notes are turned off.
+    /// Positions past the end of the file stop playback.
+    pub fn seek(&self, position: Duration) {
+        self.update(|transport| transport.jump(to_micros(position)))
+    }
+
+    /// Sets a region that is played repeatedly once the playback position enters it,
+    /// or plays the file to the end if `region` is `None`.
+    pub fn set_loop(&self, region: Option<Range<Duration>>) {
+        self.update(|transport| {
+            transport.loop_region = region
+                .map(|region| to_micros(region.start)..to_micros(region.end))
+                .filter(|region| region.start < region.end);
+        })
+    }
+
+    /// Stops playback, all sounding notes are turned off.
+    /// A stopped player can be started again with `Player::run` or `Player::spawn`.
+    pub fn stop(&self) {
+        self.update(|transport| transport.state = PlayState::Stopped)
+    }
+}
+
+/// Keeps track of the notes that are turned on, to turn them off on pause, seek and stop.
+struct ActiveNotes([u128; 16]);
+impl ActiveNotes {
+    fn update(&mut self, msg: MidiMessage) {
+        let channel = (msg.status & 0x0F) as usize;
+        let note = 1u128 << (msg.data1 & 0x7F);
+        match msg.status & 0xF0 {
+            0x90 if msg.data2 > 0 => self.0[channel] |= note,
+            0x80 | 0x90 => self.0[channel] &= !note,
+            _ => (),
+        }
+    }
+
+    /// Returns note off messages for all active notes, followed by sustain off and
+    /// all notes off for every channel, for devices that missed a note on.
+    fn release(&mut self) -> Vec<MidiMessage> {
+        let mut messages = Vec::new();
+        for (channel, notes) in self.0.iter_mut().enumerate() {
+            for note in (0..128).filter(|&note| *notes & 1 << note != 0) {
+                messages.push(MidiMessage::from([0x80 | channel as u8, note, 0, 0]));
+            }
+            *notes = 0;
+        }
+        for channel in 0..16 {
+            messages.push(MidiMessage::from([0xB0 | channel, CONTROL_SUSTAIN, 0, 0]));
+            messages.push(MidiMessage::from([
+                0xB0 | channel,
+                CONTROL_ALL_NOTES_OFF,
+                0,
+                0,
+            ]));
+        }
+        messages
+    }
+}
+
+/// Plays a Standard MIDI File through an `OutputPort`.
+///
+/// All tracks are merged and the tempo map is applied up front. Events are sent
+/// by a timing loop that sleeps until they are due. If the port was opened with a
+/// latency, events are sent that many milliseconds early with timestamps of the port's clock,
+/// so PortMidi delivers them without the jitter of the timing loop.
+/// Only channel messages and complete SysEx messages are sent.
+///
+/// ```no_run
+/// # use portmidi::{PortMidi, Player};
+/// # use portmidi::smf::Smf;
+/// # use std::{thread, time::Duration};
+/// let context = PortMidi::new().unwrap();
+/// let out_port = context.default_output_port(1024).unwrap();
+/// let player = Player::new(&Smf::load("song.mid").unwrap());
+/// let control = player.control();
+/// let playback = player.spawn(out_port);
+/// thread::sleep(Duration::from_secs(5));
+/// control.seek(Duration::from_secs(1));
+/// control.set_loop(Some(Duration::from_secs(1)..Duration::from_secs(3)));
+/// thread::sleep(Duration::from_secs(10));
+/// control.stop();
+/// playback.join().unwrap();
+/// ```
+pub struct Player {
+    events: Vec<TimedEvent>,
+    control: PlayerControl,
+}
+impl Player {
+    /// Creates a player for the given file, starting at the beginning.
+    pub fn new(smf: &Smf) -> Self {
+        let (events, duration) = timeline(smf);
+        let transport = Transport {
+            state: PlayState::Stopped,
+            anchor_position: 0,
+            anchor_instant: Instant::now(),
+            seek: false,
+            loop_region: None,
+        };
+        Player {
+            events,
+            control: PlayerControl {
+                shared: Arc::new((Mutex::new(transport), Condvar::new())),
+                duration,
+            },
+        }
+    }
+
+    /// Returns a handle to control playback.
+    pub fn control(&self) -> PlayerControl {
+        self.control.clone()
+    }
+
+    /// Returns the length of the file.
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.control.duration)
+    }
+
+    /// Plays the file from the current position on the calling thread.
+    ///
+    /// Returns when playback is stopped with `PlayerControl::stop` or reaches the end
+    /// of the file. All sounding notes are turned off before returning.
+    pub fn run(&self, port: &mut OutputPort) -> Result<()> {
+        let control = &self.control;
+        let lookahead = u64::from(port.latency()) * 1000;
+        let mut notes = ActiveNotes([0; 16]);
+        // the timestamp of the last scheduled event
+        let mut last_timestamp = 0;
+        let mut index;
+        {
+            let mut transport = control.lock();
+            if transport.anchor_position >= control.duration {
+                transport.anchor_position = 0;
+            }
+            let position = transport.anchor_position;
+            transport.jump(position);
+            transport.state = PlayState::Playing;
+            index = self.index_at(position);
+            transport.seek = false;
+        }
+        loop {
+            let mut batch = Vec::new();
+            let mut release = false;
+            let mut stopped = false;
+            {
+                let mut transport = control.lock();
+                loop {
+                    if transport.seek {
+                        transport.seek = false;
+                        index = self.index_at(transport.anchor_position);
+                        release = true;
+                    }
+                    if transport.state == PlayState::Stopped {
+                        stopped = true;
+                        release = true;
+                        break;
+                    }
+                    if transport.state == PlayState::Paused {
+                        if notes.0.iter().any(|&notes| notes != 0) {
+                            release = true;
+                            break;
+                        }
+                        transport = control.shared.1.wait(transport).unwrap();
+                        continue;
+                    }
+                    let position = transport.position();
+                    let loop_end = match transport.loop_region {
+                        Some(ref region) if position >= region.start => Some(region.end),
+                        _ => None,
+                    };
+                    if let Some(end) = loop_end {
+                        if position >= end {
+                            let start = transport.loop_region.as_ref().unwrap().start;
+                            transport.jump(start);
+                            continue;
+                        }
+                    }
+                    // events at the end of the file, usually note offs, are sent before stopping
+                    let end = loop_end.unwrap_or(u64::MAX);
+                    while index < self.events.len()
+                        && self.events[index].time < end
+                        && self.events[index].time <= position + lookahead
+                    {
+                        batch.push((self.events[index].time.saturating_sub(position), index));
+                        index += 1;
+                    }
+                    if !batch.is_empty() {
+                        break;
+                    }
+                    if position >= control.duration {
+                        transport.anchor_position = control.duration;
+                        transport.state = PlayState::Stopped;
+                        continue;
+                    }
+                    let next = match self.events.get(index) {
+                        Some(event) if event.time < end => event.time.saturating_sub(lookahead),
+                        _ => end.min(control.duration),
+                    };
+                    let timeout = Duration::from_micros(next.saturating_sub(position).max(1));
+                    transport = control.shared.1.wait_timeout(transport, timeout).unwrap().0;
+                }
+            }
+
+            let now = port.time();
+            if release {
+                let timestamp = if lookahead > 0 {
+                    now.max(last_timestamp)
+                } else {
+                    0
+                };
+                let events: Vec<MidiEvent> = notes
+                    .release()
+                    .into_iter()
+                    .map(|message| MidiEvent { message, timestamp })
+                    .collect();
+                port.write_events(events)?;
+            }
+            if stopped {
+                return Ok(());
+            }
+            for (delay, index) in batch {
+                let timestamp = if lookahead > 0 {
+                    now + (delay / 1000) as ffi::PmTimestamp
+                } else {
+                    0
+                };
+                last_timestamp = last_timestamp.max(timestamp);
+                match self.events[index].event {
+                    PlayEvent::Midi(message) => {
+                        notes.update(message);
+                        port.write_event(MidiEvent { message, timestamp })?;
+                    }
+                    // continuation packets can't be sent on their own
+                    PlayEvent::SysEx(ref data) if data.last() == Some(&ffi::MIDI_EOX) => {
+                        port.write_sysex(timestamp, data)?;
+                    }
+                    PlayEvent::SysEx(_) => (),
+                }
+            }
+        }
+    }
+
+    /// Moves the player and the port into a background thread that plays the file
+    /// from the current position. Use `control` to control playback.
+    pub fn spawn(self, mut port: OutputPort) -> Playback {
+        let control = self.control();
+        let thread = thread::spawn(move || {
+            let result = self.run(&mut port);
+            (port, result)
+        });
+        Playback {
+            control,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the index of the first event at or after `position`.
+    fn index_at(&self, position: u64) -> usize {
+        match self.events.binary_search_by(|event| {
+            event
+                .time
+                .cmp(&position)
+                .then(::std::cmp::Ordering::Greater)
+        }) {
+            Ok(index) | Err(index) => index,
+        }
+    }
+}
+
+/// A handle to the background thread started by `Player::spawn`.
+///
+/// Playback is stopped and the thread is joined when the handle is dropped.
+pub struct Playback {
+    control: PlayerControl,
+    thread: Option<thread::JoinHandle<(OutputPort, Result<()>)>>,
+}
+impl Playback {
+    /// Returns a handle to control playback.
+    pub fn control(&self) -> &PlayerControl {
+        &self.control
+    }
+
+    /// Waits until playback is stopped or reaches the end of the file and returns the port,
+    /// or the error that stopped playback.
+    ///
+    /// If the thread panicked, the panic is propagated to the caller.
+    pub fn join(mut self) -> Result<OutputPort> {
+        let thread = self.thread.take().expect("playback thread already joined");
+        match thread.join() {
+            Ok((port, result)) => result.map(|_| port),
+            Err(panic) => ::std::panic::resume_unwind(panic),
+        }
+    }
+}
+impl Drop for Playback {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.control.stop();
+            let _ = thread.join();
+        }
+    }
+}
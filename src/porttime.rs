@@ -0,0 +1,215 @@
+//! Safe bindings for PortTime, the millisecond timer used by PortMidi.
+//!
+//! All `MidiEvent` timestamps of ports opened without a custom `TimeSource`
+//! are PortTime milliseconds. PortMidi starts the timer on demand when a port
+//! is opened, so the timer may already be running when a `Clock` is created.
+use ffi;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::panic;
+use std::process;
+use std::ptr;
+use std::time::{Duration, Instant};
+use time::TimeSource;
+use types::{Error, Result};
+
+type Callback = Box<dyn FnMut(ffi::PmTimestamp) + Send>;
+
+/// Returns `true` if the PortTime timer is running.
+pub fn is_started() -> bool {
+    #[cfg(feature = "dynamic")]
+    {
+        if ::library::load().is_err() {
+            return false;
+        }
+    }
+    unsafe { ffi::Pt_Started() != 0 }
+}
+
+/// Returns the current PortTime in milliseconds.
+/// If the timer is not running the result is undefined, usually `0`.
+/// With the `dynamic` feature it is `0` if the PortMidi library can't be loaded.
+pub fn time() -> ffi::PmTimestamp {
+    #[cfg(feature = "dynamic")]
+    {
+        if ::library::load().is_err() {
+            return 0;
+        }
+    }
+    unsafe { ffi::Pt_Time() }
+}
+
+/// Converts a PortTime timestamp into the `Duration` since the timer was started.
+pub fn to_duration(timestamp: ffi::PmTimestamp) -> Duration {
+    Duration::from_millis(timestamp as u64)
+}
+
+/// A handle to the process wide PortTime timer.
+///
+/// A `Clock` created with `start` or `start_with_callback` owns the timer and
+/// stops it on drop, a `Clock` created with `attach` leaves it running.
+pub struct Clock {
+    origin: Instant,
+    owned: bool,
+    // Must outlive the timer, PortTime calls it until the timer is stopped
+    callback: Option<Box<Callback>>,
+}
+impl Clock {
+    /// Starts the PortTime timer with the given resolution in milliseconds.
+    /// Returns an `Error::PortTime(PtError::PtAlreadyStarted)` if the timer is already running,
+    /// use `attach` to share a running timer.
+    pub fn start(resolution: u32) -> Result<Clock> {
+        #[cfg(feature = "dynamic")]
+        ::library::load()?;
+        Result::from(unsafe { ffi::Pt_Start(resolution as c_int, None, ptr::null_mut()) })?;
+        Ok(Clock::new(true, None))
+    }
+
+    /// Starts the PortTime timer with the given resolution in milliseconds and calls
+    /// `callback` with the current time every `resolution` milliseconds.
+    /// The callback runs on the PortTime timer thread and must not block.
+    /// Returns an `Error::PortTime(PtError::PtAlreadyStarted)` if the timer is already running.
+    pub fn start_with_callback<F>(resolution: u32, callback: F) -> Result<Clock>
+    where
+        F: FnMut(ffi::PmTimestamp) + Send + 'static,
+    {
+        #[cfg(feature = "dynamic")]
+        ::library::load()?;
+        let mut callback: Box<Callback> = Box::new(Box::new(callback));
+        let user_data = &mut *callback as *mut Callback as *mut c_void;
+        Result::from(unsafe {
+            ffi::Pt_Start(resolution as c_int, Some(callback_trampoline), user_data)
+        })?;
+        Ok(Clock::new(true, Some(callback)))
+    }
+
+    /// Returns a `Clock` for the running timer, starting it with a resolution of
+    /// one millisecond if necessary, like PortMidi does when a port is opened.
+    /// The timer is not stopped when the returned `Clock` is dropped.
+    pub fn attach() -> Result<Clock> {
+        #[cfg(feature = "dynamic")]
+        ::library::load()?;
+        if !is_started() {
+            match Result::from(unsafe { ffi::Pt_Start(1, None, ptr::null_mut()) }) {
+                Err(Error::PortTime(ffi::PtError::PtAlreadyStarted)) | Ok(()) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Clock::new(false, None))
+    }
+
+    fn new(owned: bool, callback: Option<Box<Callback>>) -> Clock {
+        let now = Instant::now();
+        let origin = now.checked_sub(to_duration(time())).unwrap_or(now);
+        Clock {
+            origin,
+            owned,
+            callback,
+        }
+    }
+
+    /// Returns the current PortTime in milliseconds.
+    pub fn time(&self) -> ffi::PmTimestamp {
+        time()
+    }
+
+    /// Returns the `Instant` at which the timer was started.
+    pub fn origin(&self) -> Instant {
+        self.origin
+    }
+
+    /// Converts a PortTime timestamp into an `Instant`.
+    pub fn to_instant(&self, timestamp: ffi::PmTimestamp) -> Instant {
+        self.origin + to_duration(timestamp)
+    }
+
+    /// Returns the time elapsed since the given PortTime timestamp,
+    /// or a zero `Duration` if the timestamp lies in the future.
+    pub fn elapsed_since(&self, timestamp: ffi::PmTimestamp) -> Duration {
+        elapsed(self.time(), timestamp)
+    }
+
+    /// Returns `true` if this `Clock` stops the timer on drop.
+    pub fn is_owned(&self) -> bool {
+        self.owned
+    }
+
+    /// Stops the timer if it is owned by this `Clock`.
+    /// Returns an `Error::PortTime(_)` if PortTime fails to stop the timer,
+    /// dropping the `Clock` ignores the error.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop_timer()
+    }
+
+    fn stop_timer(&mut self) -> Result<()> {
+        if self.owned {
+            Result::from(unsafe { ffi::Pt_Stop() })?;
+            self.owned = false;
+        }
+        // the timer thread has been joined, the callback can't be called anymore
+        self.callback = None;
+        Ok(())
+    }
+}
+// The callback is only accessed by the timer thread and through `&mut self`
+unsafe impl Sync for Clock {}
+impl TimeSource for Clock {
+    fn now(&self) -> ffi::PmTimestamp {
+        self.time()
+    }
+}
+impl Drop for Clock {
+    fn drop(&mut self) {
+        if self.stop_timer().is_err() {
+            // the timer may still call the callback
+            mem::forget(self.callback.take());
+        }
+    }
+}
+
+/// Returns the time from `timestamp` to `now`, zero if `timestamp` lies in the future.
+fn elapsed(now: ffi::PmTimestamp, timestamp: ffi::PmTimestamp) -> Duration {
+    to_duration(now.saturating_sub(timestamp))
+}
+
+extern "C" fn callback_trampoline(timestamp: ffi::PmTimestamp, user_data: *mut c_void) {
+    let callback = unsafe { &mut *(user_data as *mut Callback) };
+    // unwinding into C is undefined behaviour
+    panic::catch_unwind(panic::AssertUnwindSafe(|| callback(timestamp)))
+        .unwrap_or_else(|_| process::abort())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{elapsed, to_duration, Clock};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn timestamps_are_milliseconds() {
+        assert_eq!(to_duration(0), Duration::from_millis(0));
+        assert_eq!(to_duration(1500), Duration::from_millis(1500));
+        assert_eq!(
+            to_duration(u32::MAX),
+            Duration::from_millis(u64::from(u32::MAX))
+        );
+    }
+
+    #[test]
+    fn timestamps_are_relative_to_the_origin() {
+        let origin = Instant::now();
+        let clock = Clock {
+            origin,
+            owned: false,
+            callback: None,
+        };
+        assert_eq!(clock.to_instant(0), origin);
+        assert_eq!(clock.to_instant(250), origin + Duration::from_millis(250));
+    }
+
+    #[test]
+    fn elapsed_saturates_for_future_timestamps() {
+        assert_eq!(elapsed(1000, 400), Duration::from_millis(600));
+        assert_eq!(elapsed(1000, 1000), Duration::from_millis(0));
+        assert_eq!(elapsed(1000, 1200), Duration::from_millis(0));
+    }
+}
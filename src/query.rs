@@ -0,0 +1,119 @@
+use device::DeviceInfo;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum NamePattern {
+    Exact(String),
+    Contains(String),
+    Glob(String),
+}
+
+/// Selects devices for `PortMidi::find_input` and `PortMidi::find_output`.
+///
+/// A query without criteria matches every device, the criteria are combined.
+/// Substring and glob patterns ignore case, a glob supports `*` for any
+/// sequence of characters and `?` for a single character.
+///
+/// ```
+/// # use portmidi::DeviceQuery;
+/// let query = DeviceQuery::new().name_glob("launchpad*").interface("ALSA");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceQuery {
+    name: Option<NamePattern>,
+    interface: Option<String>,
+}
+
+impl DeviceQuery {
+    /// Creates a query that matches every device.
+    pub fn new() -> Self {
+        DeviceQuery::default()
+    }
+
+    /// Matches devices with exactly this name.
+    pub fn name(self, name: &str) -> Self {
+        self.with_name(NamePattern::Exact(name.to_owned()))
+    }
+
+    /// Matches devices whose name contains `part`.
+    pub fn name_contains(self, part: &str) -> Self {
+        self.with_name(NamePattern::Contains(part.to_lowercase()))
+    }
+
+    /// Matches devices whose name matches the glob `pattern`, e.g. `Launchpad*Out?`.
+    pub fn name_glob(self, pattern: &str) -> Self {
+        self.with_name(NamePattern::Glob(pattern.to_lowercase()))
+    }
+
+    /// Matches devices of the MIDI API `interface`, e.g. `ALSA` or `CoreMIDI`.
+    pub fn interface(mut self, interface: &str) -> Self {
+        self.interface = Some(interface.to_owned());
+        self
+    }
+
+    fn with_name(mut self, pattern: NamePattern) -> Self {
+        self.name = Some(pattern);
+        self
+    }
+
+    /// Returns `true` if the device matches all criteria.
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        let name = match self.name {
+            Some(NamePattern::Exact(ref name)) => device.name() == name,
+            Some(NamePattern::Contains(ref part)) => device.name().to_lowercase().contains(part),
+            Some(NamePattern::Glob(ref pattern)) => {
+                let name: Vec<char> = device.name().to_lowercase().chars().collect();
+                let pattern: Vec<char> = pattern.chars().collect();
+                glob_matches(&pattern, &name)
+            }
+            None => true,
+        };
+        let interface = match self.interface {
+            Some(ref interface) => device.interface() == interface,
+            None => true,
+        };
+        name && interface
+    }
+
+    /// Returns the best of the matching devices: one whose name equals the pattern,
+    /// then one that is not opened, then the one with the shortest name.
+    pub(crate) fn best_match<'a, I>(&self, devices: I) -> Option<&'a DeviceInfo>
+    where
+        I: IntoIterator<Item = &'a DeviceInfo>,
+    {
+        devices
+            .into_iter()
+            .filter(|device| self.matches(device))
+            .min_by_key(|device| {
+                let equal = match self.name {
+                    Some(NamePattern::Contains(ref part)) => device.name().to_lowercase() == *part,
+                    _ => true,
+                };
+                (!equal, device.is_opened(), device.name().len())
+            })
+    }
+}
+
+impl fmt::Display for DeviceQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(NamePattern::Exact(ref name)) => write!(f, "name \"{}\"", name)?,
+            Some(NamePattern::Contains(ref part)) => write!(f, "name containing \"{}\"", part)?,
+            Some(NamePattern::Glob(ref pattern)) => write!(f, "name like \"{}\"", pattern)?,
+            None => write!(f, "any name")?,
+        }
+        if let Some(ref interface) = self.interface {
+            write!(f, " on interface \"{}\"", interface)?;
+        }
+        Ok(())
+    }
+}
+
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..=name.len()).any(|skip| glob_matches(rest, &name[skip..])),
+        Some((&'?', rest)) => !name.is_empty() && glob_matches(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_matches(rest, &name[1..]),
+    }
+}
@@ -0,0 +1,226 @@
+use ffi;
+use io::InputPort;
+use smf::{self, EventKind, Format, MetaEvent, Smf, Timing, Track, TrackEvent};
+use std::path::Path;
+use sysex::{InputMessage, SysExAssembler, DEFAULT_MAX_SYSEX_LEN};
+use types::*;
+
+/// The layout of the file written by a `Recorder`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordFormat {
+    /// A format 0 file with all events in a single track.
+    SingleTrack,
+    /// A format 1 file with a conductor track holding the tempo and SysEx messages,
+    /// followed by one track per recorded channel.
+    PerChannel,
+}
+
+/// Records the events of an `InputPort` into a Standard MIDI File.
+///
+/// Event timestamps are converted from milliseconds to ticks using a fixed tempo.
+/// Channel messages and SysEx messages are recorded, realtime and system common
+/// messages have no representation in a file and are dropped.
+///
+/// ```no_run
+/// # use portmidi::{PortMidi, Recorder, RecordFormat};
+/// # use std::{thread, time::Duration};
+/// let context = PortMidi::new().unwrap();
+/// let in_port = context.default_input_port(1024).unwrap();
+/// let mut recorder = Recorder::new(480, 500_000).with_format(RecordFormat::PerChannel);
+/// for _ in 0..1000 {
+///     recorder.capture(&in_port).unwrap();
+///     thread::sleep(Duration::from_millis(10));
+/// }
+/// recorder.save("take.mid").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    ppq: u16,
+    tempo: u32,
+    format: RecordFormat,
+    start: Option<ffi::PmTimestamp>,
+    assembler: SysExAssembler,
+    events: Vec<(ffi::PmTimestamp, EventKind)>,
+}
+impl Recorder {
+    /// Creates a recorder writing a format 0 file with `ppq` ticks per quarter note
+    /// and a tempo of `tempo` microseconds per quarter note.
+    pub fn new(ppq: u16, tempo: u32) -> Self {
+        Recorder {
+            ppq,
+            tempo,
+            format: RecordFormat::SingleTrack,
+            start: None,
+            assembler: SysExAssembler::new(DEFAULT_MAX_SYSEX_LEN),
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets the layout of the written file.
+    pub fn with_format(mut self, format: RecordFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the maximum length of recorded SysEx messages.
+    pub fn with_max_sysex_len(mut self, max_len: usize) -> Self {
+        self.assembler = SysExAssembler::new(max_len);
+        self
+    }
+
+    /// Sets the timestamp of tick 0, e.g. `porttime::time()` when recording is armed.
+    /// By default the timestamp of the first recorded event is used.
+    pub fn start_at(&mut self, timestamp: ffi::PmTimestamp) {
+        self.start = Some(timestamp);
+    }
+
+    /// Returns the number of recorded events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns `true` if no event has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Records a single received event.
+    ///
+    /// SysEx fragments are collected until the message is complete. If a SysEx message
+    /// is too long or aborted it is dropped and an `Error::SysEx(_)` is returned,
+    /// recording can continue afterwards.
+    pub fn record(&mut self, event: MidiEvent) -> Result<()> {
+        let mut messages = Vec::new();
+        self.assembler.push(event, &mut messages);
+        let mut result = Ok(());
+        for message in messages {
+            match message {
+                // only channel messages can be stored in a file
+                Ok(InputMessage::Event(event)) if (0x80..0xF0).contains(&event.message.status) => {
+                    let msg = MidiMessage {
+                        data3: 0,
+                        ..event.message
+                    };
+                    self.push(event.timestamp, EventKind::Midi(msg));
+                }
+                Ok(InputMessage::Event(_)) => (),
+                Ok(InputMessage::SysEx(sysex)) => {
+                    self.push(sysex.timestamp, EventKind::SysEx(sysex.data))
+                }
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Records all events that are currently available on the port.
+    /// Returns the number of events read from the port.
+    pub fn capture(&mut self, port: &InputPort) -> Result<usize> {
+        let mut count = 0;
+        let mut result = Ok(());
+        while let Some(events) = port.read_n(port.buffer_size())? {
+            if events.is_empty() {
+                break;
+            }
+            count += events.len();
+            for event in events {
+                if let (Err(err), true) = (self.record(event), result.is_ok()) {
+                    result = Err(err);
+                }
+            }
+        }
+        result.map(|_| count)
+    }
+
+    fn push(&mut self, timestamp: ffi::PmTimestamp, kind: EventKind) {
+        if self.start.is_none() {
+            self.start = Some(timestamp);
+        }
+        self.events.push((timestamp, kind));
+    }
+
+    fn ticks(&self, timestamp: ffi::PmTimestamp) -> u64 {
+        let millis = u64::from(timestamp.saturating_sub(self.start.unwrap_or(0)));
+        let tempo = u64::from(self.tempo.max(1));
+        (millis * 1000 * u64::from(self.ppq) + tempo / 2) / tempo
+    }
+
+    /// Stops recording and returns the recorded file.
+    pub fn stop(self) -> Smf {
+        let tempo = TrackEvent::new(0, EventKind::Meta(MetaEvent::Tempo(self.tempo)));
+        let mut conductor = TimedTrack::new(vec![tempo]);
+        let mut channels: Vec<Option<TimedTrack>> = (0..16).map(|_| None).collect();
+        let mut last_ticks = 0;
+        for &(timestamp, ref kind) in &self.events {
+            // timestamps of interleaved messages may be slightly out of order
+            let ticks = self.ticks(timestamp).max(last_ticks);
+            last_ticks = ticks;
+            let track = match (self.format, kind) {
+                (RecordFormat::PerChannel, &EventKind::Midi(msg)) => {
+                    channels[(msg.status & 0x0F) as usize].get_or_insert_with(|| {
+                        let name = format!("Channel {}", (msg.status & 0x0F) + 1);
+                        TimedTrack::new(vec![TrackEvent::new(
+                            0,
+                            EventKind::Meta(MetaEvent::TrackName(name.into_bytes())),
+                        )])
+                    })
+                }
+                _ => &mut conductor,
+            };
+            track.push(ticks, kind.clone());
+        }
+        let mut smf = Smf::new(
+            match self.format {
+                RecordFormat::SingleTrack => Format::SingleTrack,
+                RecordFormat::PerChannel => Format::MultiTrack,
+            },
+            Timing::Metrical(self.ppq),
+        );
+        smf.tracks.push(conductor.finish(last_ticks));
+        for track in channels.into_iter().flatten() {
+            smf.tracks.push(track.finish(last_ticks));
+        }
+        smf
+    }
+
+    /// Stops recording and writes the recorded file to the given path.
+    pub fn save<P: AsRef<Path>>(self, path: P) -> smf::Result<()> {
+        self.stop().save(path)
+    }
+}
+
+/// Builds a track from events with absolute tick positions.
+struct TimedTrack {
+    events: Vec<TrackEvent>,
+    ticks: u64,
+}
+impl TimedTrack {
+    fn new(events: Vec<TrackEvent>) -> Self {
+        TimedTrack { events, ticks: 0 }
+    }
+
+    fn push(&mut self, ticks: u64, kind: EventKind) {
+        let mut delta = ticks - self.ticks;
+        // deltas are limited to 28 bits
+        while delta > 0x0FFF_FFFF {
+            self.events.push(TrackEvent::new(
+                0x0FFF_FFFF,
+                EventKind::Meta(MetaEvent::Text(Vec::new())),
+            ));
+            delta -= 0x0FFF_FFFF;
+        }
+        self.events.push(TrackEvent::new(delta as u32, kind));
+        self.ticks = ticks;
+    }
+
+    fn finish(mut self, ticks: u64) -> Track {
+        self.push(ticks, EventKind::Meta(MetaEvent::EndOfTrack));
+        Track {
+            events: self.events,
+        }
+    }
+}
@@ -0,0 +1,642 @@
+//! Reading and writing Standard MIDI Files (SMF).
+//!
+//! Channel events are mapped to `MidiMessage`s, SysEx events keep the leading `0xF0`
+//! so they can be passed to `OutputPort::write_sysex` directly.
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::result;
+use types::MidiMessage;
+
+/// SMF result type.
+pub type Result<T> = result::Result<T, SmfError>;
+
+/// Error returned when reading or writing a Standard MIDI File fails.
+#[derive(Debug)]
+pub enum SmfError {
+    Io(io::Error),
+    /// The data ended in the middle of a chunk or event.
+    UnexpectedEof,
+    /// The file doesn't start with a valid `MThd` header chunk.
+    InvalidHeader,
+    /// The format in the header is not 0, 1 or 2.
+    InvalidFormat(u16),
+    /// A format 0 file must contain exactly one track, other files at most 65535.
+    InvalidTrackCount(usize),
+    /// The ticks per quarter note of `Timing::Metrical` don't fit into 15 bits.
+    InvalidDivision(u16),
+    /// A variable length quantity is longer than four bytes or doesn't fit into 28 bits.
+    InvalidVlq,
+    /// A data byte was found where a status byte was expected and there was no running status.
+    MissingStatus,
+    /// A status byte that is not allowed in a track, e.g. a system common message.
+    InvalidStatus(u8),
+    /// A data byte of a channel message has the high bit set.
+    InvalidDataByte(u8),
+    /// The data of a meta event doesn't match the length required by its type.
+    InvalidMetaEvent(u8),
+}
+impl fmt::Display for SmfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SmfError::Io(ref err) => write!(f, "{}", err),
+            SmfError::InvalidFormat(format) => write!(f, "invalid SMF format: {}", format),
+            SmfError::InvalidTrackCount(count) => write!(f, "invalid SMF track count: {}", count),
+            SmfError::InvalidDivision(ticks) => {
+                write!(f, "invalid ticks per quarter note: {}", ticks)
+            }
+            SmfError::InvalidStatus(status) => write!(f, "invalid status byte: {:#04x}", status),
+            SmfError::InvalidDataByte(byte) => write!(f, "invalid data byte: {:#04x}", byte),
+            SmfError::InvalidMetaEvent(kind) => write!(f, "invalid meta event: {:#04x}", kind),
+            ref err => write!(f, "{:?}", err),
+        }
+    }
+}
+impl error::Error for SmfError {
+    fn description(&self) -> &str {
+        match *self {
+            SmfError::Io(_) => "portmidi-rs: SMF I/O error",
+            SmfError::UnexpectedEof => "portmidi-rs: Unexpected end of SMF data",
+            SmfError::InvalidHeader => "portmidi-rs: Invalid SMF header",
+            SmfError::InvalidFormat(_) => "portmidi-rs: Invalid SMF format",
+            SmfError::InvalidTrackCount(_) => "portmidi-rs: Invalid SMF track count",
+            SmfError::InvalidDivision(_) => "portmidi-rs: Invalid SMF division",
+            SmfError::InvalidVlq => "portmidi-rs: Invalid variable length quantity",
+            SmfError::MissingStatus => "portmidi-rs: Missing status byte",
+            SmfError::InvalidStatus(_) => "portmidi-rs: Invalid status byte",
+            SmfError::InvalidDataByte(_) => "portmidi-rs: Invalid data byte",
+            SmfError::InvalidMetaEvent(_) => "portmidi-rs: Invalid meta event",
+        }
+    }
+}
+impl From<io::Error> for SmfError {
+    fn from(err: io::Error) -> Self {
+        SmfError::Io(err)
+    }
+}
+
+/// The SMF format stored in the header chunk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// Format 0, a single multi-channel track.
+    SingleTrack,
+    /// Format 1, simultaneous tracks sharing the tempo map of the first track.
+    MultiTrack,
+    /// Format 2, independent single-track patterns.
+    MultiSong,
+}
+
+/// The meaning of delta times, stored in the header chunk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Timing {
+    /// Ticks per quarter note.
+    Metrical(u16),
+    /// SMPTE frames per second (24, 25, 29 for 30 drop frame or 30) and ticks per frame.
+    Timecode { fps: u8, ticks_per_frame: u8 },
+}
+
+/// A meta event.
+///
+/// Text events are stored as raw bytes because the encoding is not specified.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MetaEvent {
+    SequenceNumber(Option<u16>),
+    Text(Vec<u8>),
+    Copyright(Vec<u8>),
+    TrackName(Vec<u8>),
+    InstrumentName(Vec<u8>),
+    Lyric(Vec<u8>),
+    Marker(Vec<u8>),
+    CuePoint(Vec<u8>),
+    ChannelPrefix(u8),
+    Port(u8),
+    EndOfTrack,
+    /// Microseconds per quarter note.
+    Tempo(u32),
+    SmpteOffset {
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+        frames: u8,
+        subframes: u8,
+    },
+    /// The denominator is a power of two, e.g. `2` for quarter notes.
+    TimeSignature {
+        numerator: u8,
+        denominator: u8,
+        clocks_per_click: u8,
+        notated_32nds_per_quarter: u8,
+    },
+    /// `sharps` is negative for flats.
+    KeySignature {
+        sharps: i8,
+        minor: bool,
+    },
+    SequencerSpecific(Vec<u8>),
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+impl MetaEvent {
+    fn parse(kind: u8, data: &[u8]) -> Result<MetaEvent> {
+        let check_len = |len: usize| {
+            if data.len() == len {
+                Ok(())
+            } else {
+                Err(SmfError::InvalidMetaEvent(kind))
+            }
+        };
+        let event = match kind {
+            0x00 => match data.len() {
+                0 => MetaEvent::SequenceNumber(None),
+                2 => MetaEvent::SequenceNumber(Some(u16::from(data[0]) << 8 | u16::from(data[1]))),
+                _ => return Err(SmfError::InvalidMetaEvent(kind)),
+            },
+            0x01 => MetaEvent::Text(data.to_vec()),
+            0x02 => MetaEvent::Copyright(data.to_vec()),
+            0x03 => MetaEvent::TrackName(data.to_vec()),
+            0x04 => MetaEvent::InstrumentName(data.to_vec()),
+            0x05 => MetaEvent::Lyric(data.to_vec()),
+            0x06 => MetaEvent::Marker(data.to_vec()),
+            0x07 => MetaEvent::CuePoint(data.to_vec()),
+            0x20 => {
+                check_len(1)?;
+                MetaEvent::ChannelPrefix(data[0])
+            }
+            0x21 => {
+                check_len(1)?;
+                MetaEvent::Port(data[0])
+            }
+            0x2F => {
+                check_len(0)?;
+                MetaEvent::EndOfTrack
+            }
+            0x51 => {
+                check_len(3)?;
+                MetaEvent::Tempo(
+                    u32::from(data[0]) << 16 | u32::from(data[1]) << 8 | u32::from(data[2]),
+                )
+            }
+            0x54 => {
+                check_len(5)?;
+                MetaEvent::SmpteOffset {
+                    hours: data[0],
+                    minutes: data[1],
+                    seconds: data[2],
+                    frames: data[3],
+                    subframes: data[4],
+                }
+            }
+            0x58 => {
+                check_len(4)?;
+                MetaEvent::TimeSignature {
+                    numerator: data[0],
+                    denominator: data[1],
+                    clocks_per_click: data[2],
+                    notated_32nds_per_quarter: data[3],
+                }
+            }
+            0x59 => {
+                check_len(2)?;
+                MetaEvent::KeySignature {
+                    sharps: data[0] as i8,
+                    minor: data[1] != 0,
+                }
+            }
+            0x7F => MetaEvent::SequencerSpecific(data.to_vec()),
+            _ => MetaEvent::Unknown {
+                kind,
+                data: data.to_vec(),
+            },
+        };
+        Ok(event)
+    }
+
+    fn kind_and_data(&self) -> (u8, Vec<u8>) {
+        match *self {
+            MetaEvent::SequenceNumber(None) => (0x00, vec![]),
+            MetaEvent::SequenceNumber(Some(number)) => {
+                (0x00, vec![(number >> 8) as u8, number as u8])
+            }
+            MetaEvent::Text(ref text) => (0x01, text.clone()),
+            MetaEvent::Copyright(ref text) => (0x02, text.clone()),
+            MetaEvent::TrackName(ref text) => (0x03, text.clone()),
+            MetaEvent::InstrumentName(ref text) => (0x04, text.clone()),
+            MetaEvent::Lyric(ref text) => (0x05, text.clone()),
+            MetaEvent::Marker(ref text) => (0x06, text.clone()),
+            MetaEvent::CuePoint(ref text) => (0x07, text.clone()),
+            MetaEvent::ChannelPrefix(channel) => (0x20, vec![channel]),
+            MetaEvent::Port(port) => (0x21, vec![port]),
+            MetaEvent::EndOfTrack => (0x2F, vec![]),
+            MetaEvent::Tempo(tempo) => (
+                0x51,
+                vec![(tempo >> 16) as u8, (tempo >> 8) as u8, tempo as u8],
+            ),
+            MetaEvent::SmpteOffset {
+                hours,
+                minutes,
+                seconds,
+                frames,
+                subframes,
+            } => (0x54, vec![hours, minutes, seconds, frames, subframes]),
+            MetaEvent::TimeSignature {
+                numerator,
+                denominator,
+                clocks_per_click,
+                notated_32nds_per_quarter,
+            } => (
+                0x58,
+                vec![
+                    numerator,
+                    denominator,
+                    clocks_per_click,
+                    notated_32nds_per_quarter,
+                ],
+            ),
+            MetaEvent::KeySignature { sharps, minor } => (0x59, vec![sharps as u8, minor as u8]),
+            MetaEvent::SequencerSpecific(ref data) => (0x7F, data.clone()),
+            MetaEvent::Unknown { kind, ref data } => (kind, data.clone()),
+        }
+    }
+}
+
+/// The content of a `TrackEvent`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    /// A channel message, `data3` is always `0`.
+    Midi(MidiMessage),
+    /// A SysEx message starting with `0xF0`, usually terminated by `0xF7`.
+    SysEx(Vec<u8>),
+    /// Arbitrary data stored in an `0xF7` escape event, e.g. a SysEx continuation packet.
+    Escape(Vec<u8>),
+    Meta(MetaEvent),
+}
+
+/// An event of a track with the number of ticks since the previous event.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrackEvent {
+    pub delta: u32,
+    pub kind: EventKind,
+}
+impl TrackEvent {
+    pub fn new(delta: u32, kind: EventKind) -> Self {
+        TrackEvent { delta, kind }
+    }
+}
+
+/// A track chunk.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Track {
+    pub events: Vec<TrackEvent>,
+}
+impl Track {
+    pub fn new() -> Self {
+        Track { events: Vec::new() }
+    }
+
+    /// Returns the text of the first `TrackName` meta event, if any.
+    pub fn name(&self) -> Option<&[u8]> {
+        self.events
+            .iter()
+            .filter_map(|event| match event.kind {
+                EventKind::Meta(MetaEvent::TrackName(ref name)) => Some(&name[..]),
+                _ => None,
+            })
+            .next()
+    }
+}
+
+/// A Standard MIDI File.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Smf {
+    pub format: Format,
+    pub timing: Timing,
+    pub tracks: Vec<Track>,
+}
+impl Smf {
+    /// Creates an empty file without tracks.
+    pub fn new(format: Format, timing: Timing) -> Self {
+        Smf {
+            format,
+            timing,
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Parses a file from memory.
+    /// Unknown chunks are skipped.
+    pub fn parse(data: &[u8]) -> Result<Smf> {
+        let mut cursor = Cursor { data, pos: 0 };
+        if cursor.bytes(4)? != b"MThd" {
+            return Err(SmfError::InvalidHeader);
+        }
+        let header_len = cursor.u32()? as usize;
+        if header_len < 6 {
+            return Err(SmfError::InvalidHeader);
+        }
+        let mut header = Cursor {
+            data: cursor.bytes(header_len)?,
+            pos: 0,
+        };
+        let format = match header.u16()? {
+            0 => Format::SingleTrack,
+            1 => Format::MultiTrack,
+            2 => Format::MultiSong,
+            format => return Err(SmfError::InvalidFormat(format)),
+        };
+        let track_count = header.u16()? as usize;
+        if format == Format::SingleTrack && track_count != 1 {
+            return Err(SmfError::InvalidTrackCount(track_count));
+        }
+        let division = header.u16()?;
+        let timing = if division & 0x8000 == 0 {
+            Timing::Metrical(division)
+        } else {
+            Timing::Timecode {
+                fps: ((division >> 8) as i8).wrapping_neg() as u8,
+                ticks_per_frame: division as u8,
+            }
+        };
+
+        let mut tracks = Vec::with_capacity(track_count);
+        while tracks.len() < track_count && !cursor.is_empty() {
+            let id = cursor.bytes(4)?;
+            let len = cursor.u32()? as usize;
+            let chunk = cursor.bytes(len)?;
+            if id == b"MTrk" {
+                tracks.push(parse_track(chunk)?);
+            }
+        }
+        if tracks.len() < track_count {
+            return Err(SmfError::UnexpectedEof);
+        }
+        Ok(Smf {
+            format,
+            timing,
+            tracks,
+        })
+    }
+
+    /// Reads and parses a file from the given reader.
+    pub fn read<R: Read>(mut reader: R) -> Result<Smf> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Smf::parse(&data)
+    }
+
+    /// Reads and parses the file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Smf> {
+        Smf::read(File::open(path)?)
+    }
+
+    /// Writes the file to the given writer.
+    ///
+    /// Consecutive channel messages with the same status byte are written with running status.
+    /// An `EndOfTrack` meta event is appended to tracks that don't end with one.
+    /// Returns an `SmfError::InvalidVlq` if a delta or a length doesn't fit into 28 bits,
+    /// or an error if a channel message, the timing or the track count can't be stored.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        let track_count = self.tracks.len();
+        if (self.format == Format::SingleTrack && track_count != 1) || track_count > 0xFFFF {
+            return Err(SmfError::InvalidTrackCount(track_count));
+        }
+        let format: u16 = match self.format {
+            Format::SingleTrack => 0,
+            Format::MultiTrack => 1,
+            Format::MultiSong => 2,
+        };
+        let division = match self.timing {
+            Timing::Metrical(ticks) if ticks > 0x7FFF => {
+                return Err(SmfError::InvalidDivision(ticks))
+            }
+            Timing::Metrical(ticks) => ticks,
+            Timing::Timecode {
+                fps,
+                ticks_per_frame,
+            } => (u16::from((fps as i8).wrapping_neg() as u8) << 8) | u16::from(ticks_per_frame),
+        };
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6u32.to_be_bytes())?;
+        writer.write_all(&format.to_be_bytes())?;
+        writer.write_all(&(track_count as u16).to_be_bytes())?;
+        writer.write_all(&division.to_be_bytes())?;
+        for track in &self.tracks {
+            let chunk = write_track(track)?;
+            writer.write_all(b"MTrk")?;
+            writer.write_all(&(chunk.len() as u32).to_be_bytes())?;
+            writer.write_all(&chunk)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the file to the given path.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Returns the file as bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.write(&mut data)?;
+        Ok(data)
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Cursor<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return Err(SmfError::UnexpectedEof);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn data_byte(&mut self) -> Result<u8> {
+        match self.u8()? {
+            byte if byte & 0x80 != 0 => Err(SmfError::InvalidDataByte(byte)),
+            byte => Ok(byte),
+        }
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.pos)
+            .cloned()
+            .ok_or(SmfError::UnexpectedEof)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.bytes(4).map(|bytes| {
+            u32::from(bytes[0]) << 24
+                | u32::from(bytes[1]) << 16
+                | u32::from(bytes[2]) << 8
+                | u32::from(bytes[3])
+        })
+    }
+
+    fn vlq(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SmfError::InvalidVlq)
+    }
+}
+
+fn parse_track(data: &[u8]) -> Result<Track> {
+    let mut cursor = Cursor { data, pos: 0 };
+    let mut events = Vec::new();
+    let mut running_status = None;
+    while !cursor.is_empty() {
+        let delta = cursor.vlq()?;
+        let status = if cursor.peek()? & 0x80 != 0 {
+            cursor.u8()?
+        } else {
+            running_status.ok_or(SmfError::MissingStatus)?
+        };
+        let kind = match status {
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let data1 = cursor.data_byte()?;
+                let data2 = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => cursor.data_byte()?,
+                };
+                EventKind::Midi(MidiMessage {
+                    status,
+                    data1,
+                    data2,
+                    data3: 0,
+                })
+            }
+            0xF0 => {
+                running_status = None;
+                let len = cursor.vlq()? as usize;
+                // the length is checked against the data before allocating
+                let data = cursor.bytes(len)?;
+                let mut sysex = Vec::with_capacity(data.len() + 1);
+                sysex.push(0xF0);
+                sysex.extend_from_slice(data);
+                EventKind::SysEx(sysex)
+            }
+            0xF7 => {
+                running_status = None;
+                let len = cursor.vlq()? as usize;
+                EventKind::Escape(cursor.bytes(len)?.to_vec())
+            }
+            0xFF => {
+                running_status = None;
+                let kind = cursor.u8()?;
+                let len = cursor.vlq()? as usize;
+                EventKind::Meta(MetaEvent::parse(kind, cursor.bytes(len)?)?)
+            }
+            status => return Err(SmfError::InvalidStatus(status)),
+        };
+        let end_of_track = kind == EventKind::Meta(MetaEvent::EndOfTrack);
+        events.push(TrackEvent { delta, kind });
+        if end_of_track {
+            break;
+        }
+    }
+    Ok(Track { events })
+}
+
+fn write_vlq(data: &mut Vec<u8>, value: u32) -> Result<()> {
+    if value > 0x0FFF_FFFF {
+        return Err(SmfError::InvalidVlq);
+    }
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        data.push((value >> shift) as u8 & 0x7F | 0x80);
+        shift -= 7;
+    }
+    data.push(value as u8 & 0x7F);
+    Ok(())
+}
+
+fn write_track(track: &Track) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut running_status = None;
+    for event in &track.events {
+        write_vlq(&mut data, event.delta)?;
+        match event.kind {
+            EventKind::Midi(msg) => {
+                if msg.status < 0x80 || msg.status >= 0xF0 {
+                    return Err(SmfError::InvalidStatus(msg.status));
+                }
+                if running_status != Some(msg.status) {
+                    data.push(msg.status);
+                    running_status = Some(msg.status);
+                }
+                let data_bytes = match msg.status & 0xF0 {
+                    0xC0 | 0xD0 => &[msg.data1][..],
+                    _ => &[msg.data1, msg.data2][..],
+                };
+                if let Some(&byte) = data_bytes.iter().find(|&&byte| byte > 0x7F) {
+                    return Err(SmfError::InvalidDataByte(byte));
+                }
+                data.extend_from_slice(data_bytes);
+            }
+            EventKind::SysEx(ref sysex) => {
+                running_status = None;
+                let sysex = if sysex.first() == Some(&0xF0) {
+                    &sysex[1..]
+                } else {
+                    &sysex[..]
+                };
+                data.push(0xF0);
+                write_vlq(&mut data, sysex.len() as u32)?;
+                data.extend_from_slice(sysex);
+            }
+            EventKind::Escape(ref escape) => {
+                running_status = None;
+                data.push(0xF7);
+                write_vlq(&mut data, escape.len() as u32)?;
+                data.extend_from_slice(escape);
+            }
+            EventKind::Meta(ref meta) => {
+                running_status = None;
+                let (kind, meta_data) = meta.kind_and_data();
+                data.push(0xFF);
+                data.push(kind);
+                write_vlq(&mut data, meta_data.len() as u32)?;
+                data.extend_from_slice(&meta_data);
+            }
+        }
+    }
+    let end_of_track = EventKind::Meta(MetaEvent::EndOfTrack);
+    let has_end = track.events.last().map(|event| &event.kind) == Some(&end_of_track);
+    if !has_end {
+        data.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+    }
+    Ok(data)
+}
@@ -0,0 +1,139 @@
+use futures::channel::mpsc;
+use futures::executor;
+use futures::{SinkExt, Stream};
+use io::InputPort;
+use listener::DEFAULT_POLL_INTERVAL;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+use types::*;
+
+impl InputPort {
+    /// Turns the port into a `Stream` of `MidiEvent`s.
+    ///
+    /// The port is polled by a background thread every `DEFAULT_POLL_INTERVAL`.
+    /// Up to `buffer_size` events are queued for the consumer, when the queue is full
+    /// the thread stops reading and further events are buffered by PortMidi, which
+    /// reports `PmBufferOverflow` once its own buffer is exhausted.
+    /// Dropping the stream stops and joins the thread.
+    ///
+    /// ```no_run
+    /// # extern crate futures;
+    /// # extern crate portmidi;
+    /// # fn main() {
+    /// let context = portmidi::PortMidi::new().unwrap();
+    /// let in_port = context.default_input_port(1024).unwrap();
+    /// for event in futures::executor::block_on_stream(in_port.into_stream()) {
+    ///     println!("{:?}", event.unwrap());
+    /// }
+    /// # }
+    /// ```
+    pub fn into_stream(self) -> MidiEventStream {
+        let capacity = self.buffer_size();
+        self.into_stream_with(DEFAULT_POLL_INTERVAL, capacity)
+    }
+
+    /// Like `into_stream`, but polls the port every `wake_interval` and queues
+    /// at most `capacity` events for the consumer.
+    pub fn into_stream_with(self, wake_interval: Duration, capacity: usize) -> MidiEventStream {
+        let stop = Arc::new(AtomicBool::new(false));
+        let interval = Arc::new(AtomicU64::new(as_micros(wake_interval)));
+        // the channel holds `capacity + 1` items, one guaranteed slot for the sender
+        let (mut sender, receiver) = mpsc::channel(capacity.saturating_sub(1));
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            let interval = interval.clone();
+            move || {
+                let buffer_size = self.buffer_size();
+                while !stop.load(Ordering::SeqCst) {
+                    match self.read_n(buffer_size) {
+                        Ok(Some(ref events)) if !events.is_empty() => {
+                            for &event in events {
+                                if executor::block_on(sender.send(Ok(event))).is_err() {
+                                    // the stream has been dropped
+                                    return self;
+                                }
+                            }
+                        }
+                        Ok(_) => {
+                            let micros = interval.load(Ordering::SeqCst);
+                            thread::sleep(Duration::from_micros(micros));
+                        }
+                        Err(err) => {
+                            let _ = executor::block_on(sender.send(Err(err)));
+                            break;
+                        }
+                    }
+                }
+                self
+            }
+        });
+        MidiEventStream {
+            receiver,
+            stop,
+            interval,
+            thread: Some(thread),
+        }
+    }
+}
+
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+/// A `Stream` of the `MidiEvent`s received by an `InputPort`.
+///
+/// The stream ends after the first error.
+pub struct MidiEventStream {
+    receiver: mpsc::Receiver<Result<MidiEvent>>,
+    stop: Arc<AtomicBool>,
+    interval: Arc<AtomicU64>,
+    thread: Option<thread::JoinHandle<InputPort>>,
+}
+impl MidiEventStream {
+    /// Sets the interval at which the port is polled while no events are available.
+    pub fn set_wake_interval(&self, wake_interval: Duration) {
+        self.interval
+            .store(as_micros(wake_interval), Ordering::SeqCst);
+    }
+
+    /// Returns the interval at which the port is polled while no events are available.
+    pub fn wake_interval(&self) -> Duration {
+        Duration::from_micros(self.interval.load(Ordering::SeqCst))
+    }
+
+    /// Stops and joins the background thread and returns the port.
+    /// Events that have been queued but not consumed are lost.
+    pub fn stop(mut self) -> InputPort {
+        self.shutdown().expect("stream thread already joined")
+    }
+
+    fn shutdown(&mut self) -> Option<InputPort> {
+        self.stop.store(true, Ordering::SeqCst);
+        // unblocks a sender waiting for free capacity
+        self.receiver.close();
+        self.thread.take().map(|thread| match thread.join() {
+            Ok(port) => port,
+            Err(panic) => ::std::panic::resume_unwind(panic),
+        })
+    }
+}
+impl Stream for MidiEventStream {
+    type Item = Result<MidiEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+impl Drop for MidiEventStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.receiver.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use ffi;
+use io::InputPort;
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::mem;
+use types::*;
+
+/// The default maximum length of a reassembled SysEx message, including `0xF0` and `0xF7`.
+pub const DEFAULT_MAX_SYSEX_LEN: usize = 64 * 1024;
+
+const SYSEX_START: u8 = 0xF0;
+
+/// Error reported for a SysEx message that could not be reassembled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SysExError {
+    /// The message is longer than the configured maximum length, the data
+    /// up to the terminating `0xF7` was discarded.
+    TooLong { max_len: usize },
+    /// The message was interrupted by a status byte before the terminating `0xF7`,
+    /// `len` bytes had been received.
+    Aborted { len: usize },
+}
+impl fmt::Display for SysExError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SysExError::TooLong { max_len } => {
+                write!(f, "SysEx message longer than {} bytes", max_len)
+            }
+            SysExError::Aborted { len } => write!(f, "SysEx message aborted after {} bytes", len),
+        }
+    }
+}
+impl error::Error for SysExError {
+    fn description(&self) -> &str {
+        match *self {
+            SysExError::TooLong { .. } => "portmidi-rs: SysEx message too long",
+            SysExError::Aborted { .. } => "portmidi-rs: SysEx message aborted",
+        }
+    }
+}
+
+/// A complete SysEx message, including the leading `0xF0` and the terminating `0xF7`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SysExMessage {
+    /// The timestamp of the first fragment of the message.
+    pub timestamp: ffi::PmTimestamp,
+    pub data: Vec<u8>,
+}
+
+/// A message produced by the `SysExAssembler`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InputMessage {
+    /// Any message that isn't part of a SysEx message, including realtime
+    /// messages that were interleaved with a SysEx message.
+    Event(MidiEvent),
+    /// A reassembled SysEx message.
+    SysEx(SysExMessage),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Idle,
+    Receiving,
+    // discarding the rest of a message that is too long
+    Skipping,
+}
+
+/// Reassembles the SysEx fragments delivered by PortMidi into complete messages.
+///
+/// PortMidi delivers SysEx data packed four bytes per `MidiEvent`, starting with the
+/// `0xF0` status byte in `MidiMessage::status`. Realtime messages may arrive in between
+/// and are passed through as separate `InputMessage::Event`s.
+#[derive(Clone, Debug)]
+pub struct SysExAssembler {
+    max_len: usize,
+    state: State,
+    timestamp: ffi::PmTimestamp,
+    buffer: Vec<u8>,
+}
+impl SysExAssembler {
+    /// Creates an assembler for messages of at most `max_len` bytes,
+    /// including `0xF0` and `0xF7`.
+    pub fn new(max_len: usize) -> Self {
+        SysExAssembler {
+            max_len,
+            state: State::Idle,
+            timestamp: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the maximum length of a message.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Returns `true` while a SysEx message is being received.
+    pub fn is_receiving(&self) -> bool {
+        self.state != State::Idle
+    }
+
+    /// Feeds a received event into the assembler and appends the resulting messages
+    /// and errors to `output`.
+    pub fn push<E>(&mut self, event: MidiEvent, output: &mut E)
+    where
+        E: Extend<Result<InputMessage>>,
+    {
+        let status = event.message.status;
+        if is_realtime(status) {
+            // PortMidi delivers realtime messages as separate events
+            output.extend(Some(Ok(InputMessage::Event(event))));
+            return;
+        }
+        if self.state != State::Idle && status >= 0x80 && status != ffi::MIDI_EOX {
+            self.abort(output);
+        }
+        if self.state == State::Idle {
+            match status {
+                SYSEX_START => {
+                    self.state = State::Receiving;
+                    self.timestamp = event.timestamp;
+                    self.buffer.clear();
+                    self.buffer.push(SYSEX_START);
+                }
+                0x80..=0xFF => {
+                    output.extend(Some(Ok(InputMessage::Event(event))));
+                    return;
+                }
+                // a fragment without a start, e.g. the rest of an aborted message
+                _ => return,
+            }
+        } else {
+            self.push_byte(status, event.timestamp, output);
+        }
+        let msg = event.message;
+        for &byte in &[msg.data1, msg.data2, msg.data3] {
+            if self.state == State::Idle {
+                break;
+            }
+            self.push_byte(byte, event.timestamp, output);
+        }
+    }
+
+    /// Discards a partially received message.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.buffer.clear();
+    }
+
+    fn push_byte<E>(&mut self, byte: u8, timestamp: ffi::PmTimestamp, output: &mut E)
+    where
+        E: Extend<Result<InputMessage>>,
+    {
+        if byte == ffi::MIDI_EOX {
+            if self.state == State::Receiving {
+                self.buffer.push(byte);
+                let data = mem::take(&mut self.buffer);
+                output.extend(Some(Ok(InputMessage::SysEx(SysExMessage {
+                    timestamp: self.timestamp,
+                    data,
+                }))));
+            }
+            self.reset();
+        } else if is_realtime(byte) {
+            let event = MidiEvent {
+                message: MidiMessage::from([byte, 0, 0, 0]),
+                timestamp,
+            };
+            output.extend(Some(Ok(InputMessage::Event(event))));
+        } else if byte >= 0x80 {
+            self.abort(output);
+        } else if self.state == State::Receiving {
+            self.buffer.push(byte);
+            // keep room for the terminating EOX
+            if self.buffer.len() >= self.max_len {
+                output.extend(Some(Err(Error::SysEx(SysExError::TooLong {
+                    max_len: self.max_len,
+                }))));
+                self.buffer.clear();
+                self.state = State::Skipping;
+            }
+        }
+    }
+
+    fn abort<E>(&mut self, output: &mut E)
+    where
+        E: Extend<Result<InputMessage>>,
+    {
+        if self.state == State::Receiving {
+            let len = self.buffer.len();
+            output.extend(Some(Err(Error::SysEx(SysExError::Aborted { len }))));
+        }
+        self.reset();
+    }
+}
+impl Default for SysExAssembler {
+    fn default() -> Self {
+        SysExAssembler::new(DEFAULT_MAX_SYSEX_LEN)
+    }
+}
+
+fn is_realtime(byte: u8) -> bool {
+    byte >= 0xF8
+}
+
+/// Reads from an `InputPort` and yields reassembled SysEx messages
+/// and all other messages in the order they were received.
+pub struct SysExReader {
+    port: InputPort,
+    assembler: SysExAssembler,
+    queue: VecDeque<Result<InputMessage>>,
+}
+impl SysExReader {
+    /// Creates a reader for messages of at most `max_len` bytes,
+    /// including `0xF0` and `0xF7`.
+    pub fn new(port: InputPort, max_len: usize) -> Self {
+        SysExReader {
+            port,
+            assembler: SysExAssembler::new(max_len),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next complete message if one is available.
+    ///
+    /// A `Result` of `None` means no complete message was available.
+    /// SysEx messages that are too long or aborted are reported as `Error::SysEx(_)`,
+    /// reading can continue afterwards.
+    /// If PortMidi fails to read from the device an `Error::PortMidi(_)` is returned.
+    pub fn read(&mut self) -> Result<Option<InputMessage>> {
+        if self.queue.is_empty() {
+            let buffer_size = self.port.buffer_size();
+            if let Some(events) = self.port.read_n(buffer_size)? {
+                for event in events {
+                    self.assembler.push(event, &mut self.queue);
+                }
+            }
+        }
+        self.queue.pop_front().map_or(Ok(None), |res| res.map(Some))
+    }
+
+    /// Returns the underlying port.
+    pub fn port(&self) -> &InputPort {
+        &self.port
+    }
+
+    /// Returns the underlying port, discarding pending messages.
+    pub fn into_inner(self) -> InputPort {
+        self.port
+    }
+}
@@ -0,0 +1,85 @@
+use ffi;
+use std::os::raw::c_void;
+use std::panic;
+use std::process;
+use std::sync::Arc;
+
+/// A clock that provides the timestamps used by an `InputPort` or `OutputPort`.
+///
+/// By default PortMidi uses PortTime, a millisecond clock started on first use.
+/// A custom `TimeSource` lets input timestamps and output scheduling share another
+/// clock, e.g. the one of an audio engine. The returned value must be in milliseconds
+/// and should be monotonic.
+///
+/// The time source is called from PortMidi, possibly on a different thread, while
+/// reading, writing or scheduling events, so it should be cheap and must not block.
+pub trait TimeSource: Send + Sync {
+    /// Returns the current time in milliseconds.
+    fn now(&self) -> ffi::PmTimestamp;
+}
+impl<F> TimeSource for F
+where
+    F: Fn() -> ffi::PmTimestamp + Send + Sync,
+{
+    fn now(&self) -> ffi::PmTimestamp {
+        self()
+    }
+}
+
+impl<T> TimeSource for Arc<T>
+where
+    T: TimeSource + ?Sized,
+{
+    fn now(&self) -> ffi::PmTimestamp {
+        (**self).now()
+    }
+}
+
+/// Keeps a `TimeSource` at a stable address so it can be passed to PortMidi as `time_info`.
+pub(crate) struct TimeProc {
+    source: Box<Arc<dyn TimeSource>>,
+}
+impl TimeProc {
+    pub fn new(source: Arc<dyn TimeSource>) -> Self {
+        TimeProc {
+            source: Box::new(source),
+        }
+    }
+
+    /// Returns the `time_proc` argument for `Pm_OpenInput`/`Pm_OpenOutput`.
+    pub fn time_proc(&self) -> Option<ffi::PmTimeProcPtr> {
+        Some(time_source_trampoline)
+    }
+
+    /// Returns the `time_info` argument for `Pm_OpenInput`/`Pm_OpenOutput`.
+    /// The pointer is valid for the lifetime of `self`.
+    pub fn time_info(&self) -> *const c_void {
+        &*self.source as *const Arc<dyn TimeSource> as *const c_void
+    }
+}
+
+extern "C" fn time_source_trampoline(time_info: *const c_void) -> ffi::PmTimestamp {
+    let source = unsafe { &*(time_info as *const Arc<dyn TimeSource>) };
+    // unwinding into C is undefined behaviour
+    panic::catch_unwind(panic::AssertUnwindSafe(|| source.now()))
+        .unwrap_or_else(|_| process::abort())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeProc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn trampoline_calls_time_source() {
+        let ticks = Arc::new(AtomicUsize::new(41));
+        let time_proc = TimeProc::new(Arc::new({
+            let ticks = ticks.clone();
+            move || ticks.fetch_add(1, Ordering::SeqCst) as u32 + 1
+        }));
+        let proc_ptr = time_proc.time_proc().unwrap();
+        assert_eq!(proc_ptr(time_proc.time_info()), 42);
+        assert_eq!(proc_ptr(time_proc.time_info()), 43);
+    }
+}
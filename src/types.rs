@@ -5,6 +5,8 @@ use std::os::raw::c_int;
 use std::result;
 
 use ffi;
+use message::MessageError;
+use sysex::SysExError;
 
 pub type PortMidiDeviceId = c_int;
 
@@ -18,28 +20,121 @@ impl From<ffi::PmError> for Result<()> {
         }
     }
 }
+impl From<ffi::PtError> for Result<()> {
+    fn from(err: ffi::PtError) -> Self {
+        match err {
+            ffi::PtError::PtNoError => Ok(()),
+            _ => Err(Error::PortTime(err)),
+        }
+    }
+}
 
 /// PortMidi error type.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Error {
     PortMidi(ffi::PmError),
+    PortTime(ffi::PtError),
     Unknown,
     Unimplemented,
     NoDefaultDevice,
     NotAnInputDevice,
     NotAnOutputDevice,
     Invalid,
+    InvalidMessage(MessageError),
+    SysEx(SysExError),
+    /// A failure of the host MIDI API, e.g. ALSA, reported by PortMidi as
+    /// `PmError::PmHostError`. The message is the host error text, e.g. the ALSA
+    /// error string. The code is the host's error number if the backend knows it,
+    /// PortMidi only exposes the text, so it is always `None` for native ports.
+    Host {
+        code: Option<i32>,
+        message: String,
+    },
+    /// No device matched a `DeviceQuery`, the candidates are the devices of the
+    /// requested direction.
+    DeviceNotFound {
+        query: String,
+        candidates: Vec<String>,
+    },
+    /// A port or virtual device is open, see `PortMidi::rescan`.
+    DevicesInUse,
+    /// The PortMidi shared library could not be loaded, see the `library` module.
+    /// The message lists the paths that were tried and why they failed.
+    LibraryNotFound(String),
+    /// The PortMidi shared library lacks a function, e.g. it is older than 2.0.
+    MissingSymbol(&'static str),
+    /// A virtual device with this name already exists for the direction and interface.
+    DuplicateVirtualName(String),
+    /// The name of a virtual device or its interface is empty, contains a NUL byte
+    /// or the interface is not supported.
+    InvalidName(String),
 }
 impl From<ffi::PmError> for Error {
     fn from(err: ffi::PmError) -> Self {
         Error::PortMidi(err)
     }
 }
+impl From<ffi::PtError> for Error {
+    fn from(err: ffi::PtError) -> Self {
+        Error::PortTime(err)
+    }
+}
+impl From<SysExError> for Error {
+    fn from(err: SysExError) -> Self {
+        Error::SysEx(err)
+    }
+}
+impl From<MessageError> for Error {
+    fn from(err: MessageError) -> Self {
+        Error::InvalidMessage(err)
+    }
+}
+#[cfg(feature = "dynamic")]
+impl From<::library::LoadError> for Error {
+    fn from(err: ::library::LoadError) -> Self {
+        match err {
+            ::library::LoadError::NotFound(attempts) => Error::LibraryNotFound(attempts),
+            ::library::LoadError::MissingSymbol(symbol) => Error::MissingSymbol(symbol),
+        }
+    }
+}
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::PortMidi(pm_err) => write!(f, "{}", pm_err),
-            err => write!(f, "{:?}", err),
+            Error::PortTime(pt_err) => write!(f, "{}", pt_err),
+            Error::InvalidMessage(msg_err) => write!(f, "{}", msg_err),
+            Error::SysEx(sysex_err) => write!(f, "{}", sysex_err),
+            Error::MissingSymbol(symbol) => {
+                write!(f, "PortMidi library lacks `{}`", symbol)
+            }
+            Error::LibraryNotFound(ref attempts) => {
+                write!(f, "PortMidi library not found: {}", attempts)
+            }
+            Error::DuplicateVirtualName(ref name) => {
+                write!(f, "a virtual device named {:?} already exists", name)
+            }
+            Error::InvalidName(ref name) => write!(f, "invalid device name {:?}", name),
+            Error::Host {
+                code: Some(code),
+                ref message,
+            } => write!(f, "PortMidi host error {}: {}", code, message),
+            Error::Host {
+                code: None,
+                ref message,
+            } => write!(f, "PortMidi host error: {}", message),
+            Error::DeviceNotFound {
+                ref query,
+                ref candidates,
+            } => {
+                write!(f, "no device with {}", query)?;
+                if candidates.is_empty() {
+                    write!(f, ", there are no candidates")
+                } else {
+                    write!(f, ", candidates: {}", candidates.join(", "))
+                }
+            }
+            ref err => write!(f, "{:?}", err),
         }
     }
 }
@@ -58,6 +153,14 @@ impl error::Error for Error {
                 ffi::PmError::PmBadData => "PortMidi: `Invalid MIDI message Data'",
                 ffi::PmError::PmInternalError => "PortMidi: `Internal PortMidi Error'",
                 ffi::PmError::PmBufferMaxSize => "PortMidi: `Buffer cannot be made larger'",
+                ffi::PmError::PmNotImplemented => "PortMidi: `Function not implemented'",
+            },
+            Error::PortTime(pt_error) => match pt_error {
+                ffi::PtError::PtNoError => "",
+                ffi::PtError::PtHostError => "PortTime: `Host error'",
+                ffi::PtError::PtAlreadyStarted => "PortTime: `Timer already started'",
+                ffi::PtError::PtAlreadyStopped => "PortTime: `Timer already stopped'",
+                ffi::PtError::PtInsufficientMemory => "PortTime: `Insufficient memory'",
             },
             Error::Unknown => "portmidi-rs: Unknown",
             Error::Unimplemented => "portmidi-rs: Unimplemented",
@@ -65,12 +168,21 @@ impl error::Error for Error {
             Error::NotAnInputDevice => "portmidi-rs: Not an input device",
             Error::NotAnOutputDevice => "portmidi-rs: Not an output device",
             Error::Invalid => "portmidi-rs: Invalid",
+            Error::InvalidMessage(_) => "portmidi-rs: Invalid MIDI message",
+            Error::SysEx(_) => "portmidi-rs: Invalid SysEx message",
+            Error::Host { .. } => "PortMidi: `Host error'",
+            Error::DeviceNotFound { .. } => "portmidi-rs: Device not found",
+            Error::DevicesInUse => "portmidi-rs: Devices in use",
+            Error::LibraryNotFound(_) => "portmidi-rs: PortMidi library not found",
+            Error::MissingSymbol(_) => "portmidi-rs: PortMidi library lacks a function",
+            Error::DuplicateVirtualName(_) => "portmidi-rs: Virtual device name already exists",
+            Error::InvalidName(_) => "portmidi-rs: Invalid device name",
         }
     }
 }
 
 /// Represents a Midi message.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub struct MidiMessage {
     pub status: u8,
     pub data1: u8,
@@ -121,7 +233,7 @@ impl Into<ffi::PmMessage> for MidiMessage {
 ///
 /// See the PortMidi documentation for how SysEx and midi realtime messages
 /// are handled
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub struct MidiEvent {
     pub message: MidiMessage,
     pub timestamp: ffi::PmTimestamp,
@@ -1,45 +1,84 @@
+use context::PortMidi;
 use device::{DeviceInfo, Direction};
-use ffi;
-use ffi::MaybeError;
-use std::ffi::CString;
-use std::os::raw::c_int;
-use std::ptr;
+use io::{InputPort, OutputPort};
+use std::fmt;
 use types::*;
 
-#[derive(Clone, Debug)]
+/// Options used to create a `VirtualDevice`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VirtualDeviceOptions {
+    interface: Option<String>,
+    unique_name: bool,
+}
+impl VirtualDeviceOptions {
+    /// Creates options for a device of the default MIDI API with exactly the given name.
+    pub fn new() -> Self {
+        VirtualDeviceOptions::default()
+    }
+
+    /// Sets the MIDI API of the device, e.g. `ALSA` or `CoreMIDI`.
+    /// An unsupported interface is reported as an `Error::InvalidName`.
+    pub fn with_interface(mut self, interface: &str) -> Self {
+        self.interface = Some(interface.to_owned());
+        self
+    }
+
+    /// If `true`, a suffix like ` 2` is appended to the name if it is already in use,
+    /// instead of returning an `Error::DuplicateVirtualName`.
+    /// The actual name is returned by `VirtualDevice::name`.
+    pub fn with_unique_name(mut self, unique_name: bool) -> Self {
+        self.unique_name = unique_name;
+        self
+    }
+
+    /// Returns the MIDI API, `None` means the default one.
+    pub fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// Returns `true` if a suffix is appended to a name that is already in use.
+    pub fn unique_name(&self) -> bool {
+        self.unique_name
+    }
+}
+
+/// A virtual device created with `PortMidi::create_virtual_input` or
+/// `PortMidi::create_virtual_output`.
+///
+/// The device is owned by the context that created it: it exists until it is deleted
+/// with `delete` or `PortMidi::delete_virtual_device`, or until the context is
+/// terminated. Dropping a `VirtualDevice` doesn't delete the device, it only releases
+/// the handle to the context.
+#[derive(Clone)]
 pub struct VirtualDevice {
     info: DeviceInfo,
+    context: PortMidi,
 }
 
 impl VirtualDevice {
-    /// Creates a virtual input/output device depending on direction argument.
-    /// Returns the device info of the created device or an Error.
+    /// Creates a virtual input/output device depending on direction argument,
+    /// in the context returned by `PortMidi::shared`.
+    /// Returns the device info of the created device or an Error, see
+    /// `PortMidi::create_virtual_input`.
     pub fn new(name: &str, direction: Direction) -> Result<Self> {
-        let c_string = CString::new(name).unwrap();
-
-        let id = match direction {
-            Direction::Input => unsafe {
-                ffi::Pm_CreateVirtualInput(c_string.as_ptr(), ptr::null(), ptr::null())
-            },
-            Direction::Output => unsafe {
-                ffi::Pm_CreateVirtualOutput(c_string.as_ptr(), ptr::null(), ptr::null())
-            },
-        };
-
-        let id = match ffi::PmError::try_from(id as c_int) {
-            Err(ffi::PmError::PmNoError) => None,
-            Err(ffi::PmError::PmInvalidDeviceId) => {
-                panic!("Device name \"{}\" already exists or is invalid!", name)
-            }
-            Err(err) => return Err(Error::PortMidi(err)),
-            Ok(id) => Some(id),
-        };
-
-        let id: PortMidiDeviceId = id.unwrap();
+        VirtualDevice::with_options(name, direction, VirtualDeviceOptions::new())
+    }
 
-        let info = DeviceInfo::new(id)?;
+    /// Like `new`, but with the given `VirtualDeviceOptions`.
+    pub fn with_options(
+        name: &str,
+        direction: Direction,
+        options: VirtualDeviceOptions,
+    ) -> Result<Self> {
+        let context = PortMidi::shared()?;
+        match direction {
+            Direction::Input => context.create_virtual_input_with(name, options),
+            Direction::Output => context.create_virtual_output_with(name, options),
+        }
+    }
 
-        Ok(VirtualDevice { info })
+    pub(crate) fn from_context(context: PortMidi, info: DeviceInfo) -> Self {
+        VirtualDevice { info, context }
     }
 
     pub fn id(&self) -> PortMidiDeviceId {
@@ -57,12 +96,36 @@ impl VirtualDevice {
     pub fn is_output(&self) -> bool {
         self.info.is_output()
     }
+
+    /// Returns the `DeviceInfo` of the device.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Opens an `InputPort` with the given buffer size, other applications send
+    /// to the device and the port receives their messages.
+    /// If the device is not an input device an `Error::NotAnInputDevice` is returned.
+    pub fn input_port(&self, buffer_size: usize) -> Result<InputPort> {
+        self.context.input_port(self.info.clone(), buffer_size)
+    }
+
+    /// Opens an `OutputPort` with the given buffer size, messages written to the port
+    /// are received by the applications connected to the device.
+    /// If the device is not an output device an `Error::NotAnOutputDevice` is returned.
+    pub fn output_port(&self, buffer_size: usize) -> Result<OutputPort> {
+        self.context.output_port(self.info.clone(), buffer_size)
+    }
+
+    /// Deletes the device, see `PortMidi::delete_virtual_device`.
+    pub fn delete(self) -> Result<()> {
+        self.context.delete_virtual_device(self.id())
+    }
 }
 
-impl Drop for VirtualDevice {
-    fn drop(&mut self) {
-        Result::from(unsafe { ffi::Pm_DeleteVirtualDevice(self.id()) })
-            .map_err(|err| println!("Error deleting virtual device: {}", err))
-            .unwrap();
+impl fmt::Debug for VirtualDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VirtualDevice")
+            .field("info", &self.info)
+            .finish()
     }
 }
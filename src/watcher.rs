@@ -0,0 +1,129 @@
+use context::PortMidi;
+use device::DeviceInfo;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use types::*;
+
+/// The rescan interval used by `DeviceWatcher::new`.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change of the device list reported by a `DeviceWatcher`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceEvent {
+    /// A device was connected, the info has the id after the rescan.
+    Added(DeviceInfo),
+    /// A device was disconnected, the info has the id before the rescan.
+    Removed(DeviceInfo),
+}
+
+/// Periodically rescans the devices of a `PortMidi` context in a background thread
+/// and calls a callback for every added or removed device.
+///
+/// Devices are compared by name and direction, as their ids may change on a rescan.
+/// `PortMidi::rescan` fails while a port is open or a virtual device exists, these
+/// rescans are skipped, so changes are only reported once all ports are closed.
+///
+/// The thread is stopped and joined when the watcher is dropped.
+pub struct DeviceWatcher {
+    // the thread stops when the sender is dropped
+    stop: Option<mpsc::Sender<()>>,
+    error: Arc<Mutex<Option<Error>>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+impl DeviceWatcher {
+    /// Starts watching the devices of `context`, rescanning every `DEFAULT_WATCH_INTERVAL`.
+    pub fn new<F>(context: PortMidi, callback: F) -> Result<DeviceWatcher>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        DeviceWatcher::with_interval(context, DEFAULT_WATCH_INTERVAL, callback)
+    }
+
+    /// Like `new`, but rescans every `interval`.
+    pub fn with_interval<F>(
+        context: PortMidi,
+        interval: Duration,
+        mut callback: F,
+    ) -> Result<DeviceWatcher>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let mut devices = context.devices()?;
+        let (stop, stopped) = mpsc::channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread = thread::spawn({
+            let error = error.clone();
+            move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let current = match context.rescan().and_then(|_| context.devices()) {
+                        Ok(current) => current,
+                        Err(Error::DevicesInUse) => continue,
+                        Err(err) => {
+                            *error.lock().unwrap() = Some(err);
+                            break;
+                        }
+                    };
+                    changes(&devices, &current)
+                        .into_iter()
+                        .for_each(&mut callback);
+                    devices = current;
+                }
+            }
+        });
+        Ok(DeviceWatcher {
+            stop: Some(stop),
+            error,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns `false` if the watcher thread has stopped because a rescan failed.
+    /// The error can be obtained with `error`.
+    pub fn is_running(&self) -> bool {
+        self.error().is_none()
+    }
+
+    /// Returns the error that stopped the watcher thread, if any.
+    pub fn error(&self) -> Option<Error> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// Stops and joins the watcher thread.
+    ///
+    /// If the callback panicked, the panic is propagated to the caller.
+    pub fn stop(mut self) {
+        self.stop.take();
+        let thread = self.thread.take().expect("watcher thread already joined");
+        if let Err(panic) = thread.join() {
+            ::std::panic::resume_unwind(panic);
+        }
+    }
+}
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Returns the devices that were removed from `old` and added in `new`.
+fn changes(old: &[DeviceInfo], new: &[DeviceInfo]) -> Vec<DeviceEvent> {
+    let same =
+        |a: &DeviceInfo, b: &DeviceInfo| a.name() == b.name() && a.direction() == b.direction();
+    let mut added: Vec<&DeviceInfo> = new.iter().collect();
+    let mut events = Vec::new();
+    for device in old {
+        match added.iter().position(|other| same(device, other)) {
+            Some(index) => {
+                added.remove(index);
+            }
+            None => events.push(DeviceEvent::Removed(device.clone())),
+        }
+    }
+    events.extend(added.into_iter().cloned().map(DeviceEvent::Added));
+    events
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "dynamic")]
+extern crate portmidi;
+
+use portmidi::{library, porttime, Error, PortMidi};
+use std::env;
+
+#[test]
+fn test_library_not_found() {
+    env::set_var("PORTMIDI_LIBRARY", "/nonexistent/libportmidi.so");
+    library::set_search_paths(vec!["/nonexistent/lib"]);
+    // the default names may still find an installed PortMidi
+    if let Err(err) = PortMidi::new() {
+        match err {
+            Error::LibraryNotFound(ref attempts) => {
+                assert!(attempts.starts_with("/nonexistent/libportmidi.so: "));
+                assert!(attempts.contains("; /nonexistent/lib: "));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+        assert!(!library::is_loaded());
+        // the other entry points report the error instead of panicking
+        assert_eq!(porttime::Clock::attach().err(), Some(err.clone()));
+        assert_eq!(portmidi::DeviceInfo::new(0).err(), Some(err));
+        assert!(!porttime::is_started());
+        assert_eq!(porttime::time(), 0);
+    }
+}
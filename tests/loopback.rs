@@ -0,0 +1,724 @@
+extern crate portmidi;
+
+use portmidi::backend::LoopbackBackend;
+use portmidi::{
+    ChannelMask, DeviceEvent, DeviceQuery, DeviceWatcher, Error, InputFilter, InputMessage,
+    MidiEvent, MidiMessage, OutputPortOptions, PmError, PortMidi, RetryPolicy, SysExReader,
+    VirtualDeviceOptions,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+fn event(raw: [u8; 4], timestamp: u32) -> MidiEvent {
+    MidiEvent {
+        message: MidiMessage::from(raw),
+        timestamp,
+    }
+}
+
+#[test]
+fn test_devices() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend).unwrap();
+    assert_eq!(context.device_count(), 2);
+    assert_eq!(context.default_input_device_id().unwrap(), input);
+    assert_eq!(context.default_output_device_id().unwrap(), output);
+    let devices = context.devices().unwrap();
+    assert!(devices[0].is_input());
+    assert!(devices[1].is_output());
+    assert_eq!(devices[1].name(), "Loop");
+    assert_eq!(
+        context.device(2).unwrap_err(),
+        Error::PortMidi(PmError::PmInvalidDeviceId)
+    );
+
+    let v_in = context.create_virtual_input("Virt in").unwrap();
+    let v_out = context.create_virtual_output("Virt out").unwrap();
+    assert!(v_in.is_input());
+    assert_eq!(context.device(v_out.id()).unwrap().name(), "Virt out");
+    // virtual devices are never the default devices
+    assert_eq!(context.default_input_device_id().unwrap(), input);
+}
+
+#[test]
+fn test_read_write() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let mut in_port = context
+        .input_port(context.device(input).unwrap(), 4)
+        .unwrap();
+    let mut out_port = context
+        .output_port(context.device(output).unwrap(), 4)
+        .unwrap();
+
+    assert!(!in_port.poll().unwrap());
+    assert_eq!(in_port.read().unwrap(), None);
+    out_port
+        .write_events(vec![
+            event([0x90, 60, 100, 0], 10),
+            event([0xFE, 0, 0, 0], 11),
+            event([0x80, 60, 0, 0], 20),
+        ])
+        .unwrap();
+    assert!(in_port.poll().unwrap());
+    // active sensing is dropped by the default filter
+    assert_eq!(
+        in_port.read_n(4).unwrap(),
+        Some(vec![
+            event([0x90, 60, 100, 0], 10),
+            event([0x80, 60, 0, 0], 20)
+        ])
+    );
+    assert_eq!(backend.sent(output).len(), 3);
+
+    in_port.set_filter(InputFilter::NOTE).unwrap();
+    in_port.set_channel_mask(ChannelMask::channel(1)).unwrap();
+    backend
+        .send(
+            input,
+            &[
+                event([0x90, 60, 100, 0], 30),
+                event([0xB0, 7, 100, 0], 31),
+                event([0xB1, 7, 100, 0], 32),
+            ],
+        )
+        .unwrap();
+    assert_eq!(in_port.read().unwrap(), Some(event([0xB1, 7, 100, 0], 32)));
+    assert_eq!(in_port.read().unwrap(), None);
+
+    // the buffer holds 4 events, the overflow is reported once
+    in_port.set_filter(InputFilter::empty()).unwrap();
+    backend
+        .send(input, &[event([0xF8, 0, 0, 0], 40); 6])
+        .unwrap();
+    assert_eq!(
+        in_port.read_n(4).unwrap_err(),
+        Error::PortMidi(PmError::PmBufferOverflow)
+    );
+    assert_eq!(in_port.read_n(8).unwrap().unwrap().len(), 4);
+}
+
+#[test]
+fn test_read_into() {
+    let backend = LoopbackBackend::new();
+    let (input, _) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let mut in_port = context
+        .input_port(context.device(input).unwrap(), 4)
+        .unwrap();
+    let sent: Vec<_> = (0..6).map(|i| event([0x90, 60 + i, 100, 0], 0)).collect();
+
+    let mut events = [MidiEvent::default(); 2];
+    assert_eq!(in_port.read_into(&mut events).unwrap(), 0);
+    backend.send(input, &sent[..3]).unwrap();
+    assert_eq!(in_port.read_into(&mut events).unwrap(), 2);
+    assert_eq!(events, [sent[0], sent[1]]);
+    // at most `buffer_size` events are read
+    let mut events = [MidiEvent::default(); 8];
+    assert_eq!(in_port.read_into(&mut events).unwrap(), 1);
+    assert_eq!(events[0], sent[2]);
+
+    backend.send(input, &sent[..4]).unwrap();
+    assert_eq!(in_port.drain().unwrap().collect::<Vec<_>>(), &sent[..4]);
+    assert_eq!(in_port.drain().unwrap().count(), 0);
+    backend.send(input, &sent[4..]).unwrap();
+    assert_eq!(in_port.drain().unwrap().collect::<Vec<_>>(), &sent[4..]);
+}
+
+#[test]
+fn test_write_slice() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    let mut out_port = context
+        .output_port(context.device(output).unwrap(), 4)
+        .unwrap();
+    let events: Vec<_> = (0..10).map(|i| event([0x90, 60 + i, 100, 0], 0)).collect();
+
+    // the events are written in chunks of `buffer_size`
+    out_port.write_slice(&events).unwrap();
+    assert_eq!(in_port.read_n(16).unwrap().unwrap(), &events[..]);
+    out_port
+        .write_iter(events.iter().map(|event| event.message))
+        .unwrap();
+    assert_eq!(in_port.read_n(16).unwrap().unwrap(), &events[..]);
+    assert_eq!(backend.sent(output).len(), 20);
+
+    // an overflow is returned unless the policy retries it, the events before the
+    // overflowing one are sent like with `Pm_Write`
+    backend.fail_write_after(2, PmError::PmBufferOverflow);
+    assert_eq!(
+        out_port.write_slice(&events[..4]).unwrap_err(),
+        Error::PortMidi(PmError::PmBufferOverflow)
+    );
+    assert_eq!(backend.sent(output), &events[..2]);
+    assert_eq!(in_port.read_n(16).unwrap().unwrap(), &events[..2]);
+
+    // a retry continues at the overflowing event, so no event is sent twice
+    out_port.set_retry_policy(RetryPolicy::new(2, Duration::from_millis(1)));
+    backend.fail_write_after(2, PmError::PmBufferOverflow);
+    out_port.write_slice(&events).unwrap();
+    assert_eq!(backend.sent(output), events);
+    assert_eq!(in_port.read_n(16).unwrap().unwrap(), events);
+    backend.fail_next(PmError::PmBufferOverflow);
+    out_port
+        .write_iter(events.iter().map(|event| event.message))
+        .unwrap();
+    assert_eq!(backend.sent(output), events);
+
+    let policy = RetryPolicy::new(8, Duration::from_millis(10));
+    assert_eq!(policy.backoff(0), Duration::from_millis(10));
+    assert_eq!(policy.backoff(2), Duration::from_millis(40));
+    assert_eq!(policy.backoff(40), Duration::from_millis(100));
+}
+
+#[test]
+fn test_close() {
+    let backend = LoopbackBackend::new();
+    let (_, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let device = context.device(output).unwrap();
+
+    let out_port = context.output_port(device.clone(), 16).unwrap();
+    out_port.close().unwrap();
+    assert!(!context.device(output).unwrap().is_opened());
+    assert!(backend.sent(output).is_empty());
+
+    // the panic messages are sent before closing, even when the port is dropped
+    let options = OutputPortOptions::new(16).with_panic_on_close(true);
+    let out_port = context.output_port_with(device.clone(), options).unwrap();
+    assert!(out_port.panic_on_close());
+    drop(out_port);
+    let sent = backend.sent(output);
+    assert_eq!(sent.len(), 32);
+    assert_eq!(sent[0], event([0xB0, 123, 0, 0], 0));
+    assert_eq!(sent[31], event([0xBF, 121, 0, 0], 0));
+
+    // the port is closed even if the panic messages fail
+    let mut out_port = context.output_port(device.clone(), 16).unwrap();
+    out_port.set_panic_on_close(true);
+    backend.fail_next(PmError::PmHostError);
+    assert_eq!(
+        out_port.close().unwrap_err(),
+        Error::PortMidi(PmError::PmHostError)
+    );
+    assert!(!context.device(output).unwrap().is_opened());
+
+    // events scheduled with a latency are sent when they are due or the port is closed
+    let clock = Arc::new(AtomicU32::new(0));
+    let time_source = {
+        let clock = clock.clone();
+        move || clock.load(Ordering::SeqCst)
+    };
+    let options = OutputPortOptions::new(16)
+        .with_latency(100)
+        .with_time_source(time_source);
+    let mut out_port = context
+        .output_port_with(device.clone(), options.clone())
+        .unwrap();
+    let events = [event([0x90, 60, 100, 0], 0), event([0x90, 62, 100, 0], 50)];
+    out_port.write_slice(&events).unwrap();
+    assert!(backend.sent(output).is_empty());
+    clock.store(100, Ordering::SeqCst);
+    assert_eq!(backend.sent(output), events[..1].to_vec());
+    out_port.close().unwrap();
+    assert_eq!(backend.sent(output), events[1..].to_vec());
+
+    // aborting discards them, the panic messages aren't sent either
+    clock.store(0, Ordering::SeqCst);
+    let mut out_port = context.output_port_with(device, options).unwrap();
+    out_port.set_panic_on_close(true);
+    out_port.write_slice(&events).unwrap();
+    out_port.abort().unwrap();
+    assert!(!context.device(output).unwrap().is_opened());
+    clock.store(1000, Ordering::SeqCst);
+    assert!(backend.sent(output).is_empty());
+}
+
+#[test]
+fn test_latency() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+
+    // without latency the timestamps are ignored
+    let mut out_port = context
+        .output_port(context.device(output).unwrap(), 16)
+        .unwrap();
+    let note = event([0x90, 60, 100, 0], 1000);
+    out_port.write_event(note).unwrap();
+    assert_eq!(in_port.read_n(16).unwrap(), Some(vec![note]));
+    assert_eq!(backend.sent(output), vec![note]);
+    drop(out_port);
+
+    // with a latency events are delivered at `timestamp + latency`
+    let clock = Arc::new(AtomicU32::new(1000));
+    let time_source = {
+        let clock = clock.clone();
+        move || clock.load(Ordering::SeqCst)
+    };
+    let options = OutputPortOptions::new(16)
+        .with_latency(10)
+        .with_time_source(time_source);
+    let mut out_port = context
+        .output_port_with(context.device(output).unwrap(), options)
+        .unwrap();
+    assert_eq!(out_port.latency(), 10);
+    out_port.write_event(note).unwrap();
+    clock.store(1009, Ordering::SeqCst);
+    assert_eq!(in_port.read_n(16).unwrap(), None);
+    assert!(backend.sent(output).is_empty());
+    clock.store(1010, Ordering::SeqCst);
+    assert_eq!(in_port.read_n(16).unwrap(), Some(vec![note]));
+    assert_eq!(backend.sent(output), vec![note]);
+}
+
+#[test]
+fn test_sysex() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    let out_port = context
+        .output_port(context.device(output).unwrap(), 16)
+        .unwrap();
+
+    let sysex = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+    out_port.write_sysex(5, &sysex).unwrap();
+    assert_eq!(
+        backend.sent(output),
+        vec![
+            event([0xF0, 0x7E, 0x7F, 0x06], 5),
+            event([0x01, 0xF7, 0, 0], 5)
+        ]
+    );
+    assert_eq!(
+        out_port.write_sysex(5, &[0x7E, 0xF7]).unwrap_err(),
+        Error::PortMidi(PmError::PmBadData)
+    );
+
+    let mut reader = SysExReader::new(in_port, 64);
+    match reader.read().unwrap() {
+        Some(InputMessage::SysEx(message)) => assert_eq!(message.data, sysex.to_vec()),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_errors() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_device = context.device(input).unwrap();
+    assert_eq!(
+        context.output_port(in_device.clone(), 16).err(),
+        Some(Error::NotAnOutputDevice)
+    );
+
+    let in_port = context.input_port(in_device.clone(), 16).unwrap();
+    // a device can only be opened once
+    assert_eq!(
+        context.input_port(in_device.clone(), 16).err(),
+        Some(Error::PortMidi(PmError::PmInvalidDeviceId))
+    );
+    backend.fail_next(PmError::PmHostError);
+    assert_eq!(
+        in_port.read_n(16).unwrap_err(),
+        Error::PortMidi(PmError::PmHostError)
+    );
+    assert_eq!(in_port.read_n(16).unwrap(), None);
+    drop(in_port);
+    assert!(context.input_port(in_device, 16).is_ok());
+
+    assert_eq!(
+        backend.send(output, &[event([0x90, 60, 100, 0], 0)]),
+        Err(Error::PortMidi(PmError::PmInvalidDeviceId))
+    );
+}
+
+#[test]
+fn test_listener() {
+    let backend = LoopbackBackend::new();
+    let (input, _) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    let (sender, received) = mpsc::channel();
+    let listener = in_port.listen_with_interval(Duration::from_millis(1), move |event| {
+        sender.send(event).unwrap();
+    });
+    assert!(listener.is_running());
+
+    let events: Vec<_> = (0..3).map(|i| event([0x90, 60 + i, 100, 0], 0)).collect();
+    backend.send(input, &events).unwrap();
+    for &event in &events {
+        let timeout = Duration::from_secs(1);
+        assert_eq!(received.recv_timeout(timeout).unwrap(), event);
+    }
+
+    // the port can be used again after stopping
+    let in_port = listener.stop();
+    assert!(context.device(input).unwrap().is_opened());
+    backend.send(input, &events[..1]).unwrap();
+    assert_eq!(in_port.read_n(16).unwrap(), Some(events[..1].to_vec()));
+
+    // dropping the listener joins the thread, which drops the port and the callback
+    let listener = in_port.listen_with_interval(Duration::from_millis(1), |_| {});
+    drop(listener);
+    assert!(!context.device(input).unwrap().is_opened());
+    assert!(received.recv().is_err());
+}
+
+#[test]
+fn test_listener_error() {
+    let backend = LoopbackBackend::new();
+    let (input, _) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    let listener = in_port.listen_with_interval(Duration::from_millis(1), |_| {});
+    backend.fail_next(PmError::PmBufferOverflow);
+    for _ in 0..1000 {
+        if !listener.is_running() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!listener.is_running());
+    assert_eq!(
+        listener.error(),
+        Some(Error::PortMidi(PmError::PmBufferOverflow))
+    );
+    // the port is returned after a read error as well
+    let in_port = listener.stop();
+    assert_eq!(in_port.read_n(16).unwrap(), None);
+}
+
+#[test]
+fn test_rescan() {
+    let backend = LoopbackBackend::new();
+    let (input, _) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let (sender, events) = mpsc::channel();
+    let watcher =
+        DeviceWatcher::with_interval(context.clone(), Duration::from_millis(1), move |event| {
+            sender.send(event).unwrap()
+        })
+        .unwrap();
+
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    assert_eq!(context.rescan(), Err(Error::DevicesInUse));
+    drop(in_port);
+
+    let added = backend.add_input("Controller");
+    match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+        DeviceEvent::Added(device) => {
+            assert_eq!(device.id(), added);
+            assert_eq!(device.name(), "Controller");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(context.device_count(), 3);
+
+    // the ids after the unplugged device shift down
+    backend.unplug(input).unwrap();
+    match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+        DeviceEvent::Removed(device) => assert_eq!(device.id(), input),
+        other => panic!("unexpected {:?}", other),
+    }
+    watcher.stop();
+    assert_eq!(context.device_count(), 2);
+    assert_eq!(context.device(1).unwrap().name(), "Controller");
+    assert_eq!(context.default_input_device_id(), Ok(1));
+}
+
+#[test]
+fn test_find_devices() {
+    let backend = LoopbackBackend::new();
+    backend.add_device_pair("Launchpad Mini MIDI 2");
+    let (input, output) = backend.add_device_pair("Launchpad Mini");
+    backend.add_input("Keystation 49");
+    let context = PortMidi::with_backend(backend).unwrap();
+
+    let device = context.find_output(&DeviceQuery::new()).unwrap();
+    assert!(device.is_output());
+    assert_eq!(device.interface(), "Loopback");
+    assert!(!device.is_opened());
+
+    let query = DeviceQuery::new().name_contains("launchpad");
+    assert_eq!(context.find_output(&query).unwrap().id(), output);
+    let query = DeviceQuery::new().name_glob("launchpad*2");
+    assert_eq!(
+        context.find_input(&query).unwrap().name(),
+        "Launchpad Mini MIDI 2"
+    );
+    let query = DeviceQuery::new()
+        .name("Keystation 49")
+        .interface("Loopback");
+    assert!(context.find_input(&query).is_ok());
+
+    // an opened device is only used if there is no other match
+    let _port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    let query = DeviceQuery::new().name_glob("Launchpad Mini*");
+    assert_eq!(context.find_input(&query).unwrap().id(), 0);
+
+    let query = DeviceQuery::new().name("Keystation 49");
+    let err = context.find_output(&query).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "no device with name \"Keystation 49\", candidates: \
+         \"Launchpad Mini MIDI 2\" (Loopback), \"Launchpad Mini\" (Loopback)"
+    );
+    let query = DeviceQuery::new().interface("ALSA");
+    match context.find_input(&query) {
+        Err(Error::DeviceNotFound { candidates, .. }) => assert_eq!(candidates.len(), 3),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_duplex() {
+    let backend = LoopbackBackend::new();
+    backend.add_input("Keystation 49");
+    let (input, output) = backend.add_device_pair("Synth");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+
+    let pairs = context.device_pairs().unwrap();
+    assert_eq!(pairs.len(), 1);
+    assert_eq!(pairs[0].name(), "Synth");
+    assert_eq!(pairs[0].input.id(), input);
+    assert_eq!(pairs[0].output.id(), output);
+
+    let mut port = context.duplex_port(pairs[0].clone(), 64).unwrap();
+    let request = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+    // the loopback pair echoes the request, wait for something else
+    let reply = port
+        .request(
+            &request,
+            Duration::from_millis(10),
+            |message| match *message {
+                InputMessage::SysEx(ref sysex) => sysex.data[4] == 0x02,
+                _ => false,
+            },
+        )
+        .unwrap();
+    assert_eq!(reply, None);
+    assert_eq!(backend.sent(output).len(), 2);
+
+    port.write_message(MidiMessage::from([0x90, 60, 100, 0]))
+        .unwrap();
+    match port.read().unwrap() {
+        Some(InputMessage::Event(event)) => assert_eq!(event.message.status, 0x90),
+        other => panic!("unexpected {:?}", other),
+    }
+    let (in_port, _) = port.into_parts();
+    assert_eq!(in_port.device().id(), input);
+}
+
+#[test]
+fn test_context_lifetime() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    let mut out_port = context
+        .output_port(context.device(output).unwrap(), 16)
+        .unwrap();
+    let handle = context.clone();
+    drop(context);
+    drop(handle);
+    assert!(backend.is_initialized());
+
+    // the ports can be moved into a thread, the context lives as long as they do
+    let writer = thread::spawn(move || {
+        out_port
+            .write_message(MidiMessage::from([0x90, 60, 100, 0]))
+            .unwrap();
+    });
+    writer.join().unwrap();
+    assert!(backend.is_initialized());
+    assert_eq!(in_port.read_n(16).unwrap().unwrap().len(), 1);
+    drop(in_port);
+    assert!(!backend.is_initialized());
+}
+
+#[test]
+fn test_shared_initialization() {
+    let backend = LoopbackBackend::new();
+    let (input, output) = backend.add_device_pair("Loop");
+    let first = PortMidi::with_backend(backend.clone()).unwrap();
+    let second = PortMidi::with_backend(backend.clone()).unwrap();
+    let first_port = first
+        .output_port(first.device(output).unwrap(), 16)
+        .unwrap();
+    let in_port = second
+        .input_port(second.device(input).unwrap(), 16)
+        .unwrap();
+
+    // dropping the first context doesn't terminate the backend under the second one
+    drop(first_port);
+    drop(first);
+    assert!(backend.is_initialized());
+    let mut out_port = second
+        .output_port(second.device(output).unwrap(), 16)
+        .unwrap();
+    out_port
+        .write_message(MidiMessage::from([0x90, 60, 100, 0]))
+        .unwrap();
+    assert_eq!(in_port.read_n(16).unwrap().unwrap().len(), 1);
+
+    drop(second);
+    drop(out_port);
+    assert!(backend.is_initialized());
+    drop(in_port);
+    assert!(!backend.is_initialized());
+}
+
+#[test]
+fn test_host_error() {
+    let backend = LoopbackBackend::new();
+    let (_, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let mut out_port = context
+        .output_port(context.device(output).unwrap(), 16)
+        .unwrap();
+    assert!(!out_port.has_host_error());
+
+    let host_error = Error::Host {
+        code: Some(-19),
+        message: "No such device".to_owned(),
+    };
+    backend.fail_next(host_error.clone());
+    assert!(out_port.has_host_error());
+    assert_eq!(
+        out_port.write_message(MidiMessage::from([0x90, 60, 100, 0])),
+        Err(host_error.clone())
+    );
+    assert!(!out_port.has_host_error());
+    assert_eq!(
+        host_error.to_string(),
+        "PortMidi host error -19: No such device"
+    );
+    assert_eq!(
+        Error::Host {
+            code: None,
+            message: "No such device".to_owned(),
+        }
+        .to_string(),
+        "PortMidi host error: No such device"
+    );
+}
+
+#[test]
+fn test_virtual_devices() {
+    let backend = LoopbackBackend::new();
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    assert_eq!(context.virtual_device_count(), 0);
+    let v_in = context.create_virtual_input("Virt in").unwrap();
+    let v_out = context.create_virtual_output("Virt out").unwrap();
+    assert_eq!(context.virtual_device_count(), 2);
+    assert_eq!(context.device_count(), 2);
+
+    // dropping the handle doesn't delete the device
+    let id = v_in.id();
+    drop(v_in);
+    let devices = context.virtual_devices();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].id(), id);
+    assert_eq!(devices[0].name(), "Virt in");
+
+    let in_port = devices[0].input_port(16).unwrap();
+    let mut out_port = v_out.output_port(16).unwrap();
+    assert!(v_out.input_port(16).is_err());
+    out_port
+        .write_message(MidiMessage::from([0x90, 60, 100, 0]))
+        .unwrap();
+    assert!(in_port.read_n(16).is_ok());
+
+    // an open device can't be deleted
+    assert!(context.delete_virtual_device(id).is_err());
+    drop(in_port);
+    context.delete_virtual_device(id).unwrap();
+    assert_eq!(context.virtual_device_count(), 1);
+    assert_eq!(
+        context.delete_virtual_device(id).unwrap_err(),
+        Error::PortMidi(PmError::PmInvalidDeviceId)
+    );
+    // the name is free again
+    context
+        .create_virtual_input("Virt in")
+        .unwrap()
+        .delete()
+        .unwrap();
+
+    // the remaining device is deleted with the context
+    drop(out_port);
+    drop((devices, v_out));
+    drop(context);
+    let context = PortMidi::with_backend(backend).unwrap();
+    assert!(context.create_virtual_output("Virt out").is_ok());
+}
+
+#[test]
+fn test_virtual_device_names() {
+    let context = PortMidi::with_backend(LoopbackBackend::new()).unwrap();
+    let first = context.create_virtual_input("Virt").unwrap();
+    assert_eq!(
+        context.create_virtual_input("Virt").unwrap_err(),
+        Error::DuplicateVirtualName("Virt".to_owned())
+    );
+    // the names of inputs and outputs don't collide
+    context.create_virtual_output("Virt").unwrap();
+    assert_eq!(
+        context.create_virtual_input("").unwrap_err(),
+        Error::InvalidName("".to_owned())
+    );
+    assert_eq!(
+        context.create_virtual_input("Vi\0rt").unwrap_err(),
+        Error::InvalidName("Vi\0rt".to_owned())
+    );
+
+    let unique = VirtualDeviceOptions::new().with_unique_name(true);
+    let second = context
+        .create_virtual_input_with("Virt", unique.clone())
+        .unwrap();
+    let third = context.create_virtual_input_with("Virt", unique).unwrap();
+    assert_eq!(first.name(), "Virt");
+    assert_eq!(second.name(), "Virt 2");
+    assert_eq!(third.name(), "Virt 3");
+
+    let loopback = VirtualDeviceOptions::new().with_interface("Loopback");
+    let device = context
+        .create_virtual_output_with("Virt out", loopback)
+        .unwrap();
+    assert_eq!(device.info().interface(), "Loopback");
+    assert_eq!(
+        context
+            .create_virtual_output_with(
+                "Virt out",
+                VirtualDeviceOptions::new().with_interface("ALSA")
+            )
+            .unwrap_err(),
+        Error::InvalidName("ALSA".to_owned())
+    );
+}
@@ -0,0 +1,213 @@
+extern crate portmidi;
+
+use portmidi::backend::LoopbackBackend;
+use portmidi::smf::{EventKind, Format, MetaEvent, Smf, Timing, Track, TrackEvent};
+use portmidi::{
+    MidiEvent, MidiMessage, OutputPortOptions, PlayState, Player, PortMidi, PortMidiDeviceId,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Creates a file from `(milliseconds, message)` pairs, one tick is one millisecond.
+fn song(notes: &[(u32, [u8; 4])]) -> Smf {
+    let mut track = Track::new();
+    track.events.push(TrackEvent::new(
+        0,
+        EventKind::Meta(MetaEvent::Tempo(1_000_000)),
+    ));
+    let mut last = 0;
+    for &(time, raw) in notes {
+        let kind = EventKind::Midi(MidiMessage::from(raw));
+        track.events.push(TrackEvent::new(time - last, kind));
+        last = time;
+    }
+    track
+        .events
+        .push(TrackEvent::new(0, EventKind::Meta(MetaEvent::EndOfTrack)));
+    let mut smf = Smf::new(Format::SingleTrack, Timing::Metrical(1000));
+    smf.tracks.push(track);
+    smf
+}
+
+/// Collects the events sent to `output` until `done` returns true.
+fn sent_until<F>(
+    backend: &LoopbackBackend,
+    output: PortMidiDeviceId,
+    mut done: F,
+) -> Vec<MidiMessage>
+where
+    F: FnMut(&[MidiMessage]) -> bool,
+{
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut messages = Vec::new();
+    while !done(&messages) {
+        assert!(Instant::now() < deadline, "timed out, sent {:?}", messages);
+        thread::sleep(Duration::from_millis(1));
+        messages.extend(
+            backend
+                .sent(output)
+                .iter()
+                .map(|event: &MidiEvent| event.message),
+        );
+    }
+    messages
+}
+
+fn note_on(note: u8) -> MidiMessage {
+    MidiMessage::from([0x90, note, 100, 0])
+}
+
+fn note_off(note: u8) -> MidiMessage {
+    MidiMessage::from([0x80, note, 0, 0])
+}
+
+fn setup() -> (LoopbackBackend, PortMidiDeviceId, PortMidi) {
+    let backend = LoopbackBackend::new();
+    let (_, output) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    (backend, output, context)
+}
+
+#[test]
+fn test_pause() {
+    let (backend, output, context) = setup();
+    let port = context
+        .output_port(context.device(output).unwrap(), 1024)
+        .unwrap();
+    let player = Player::new(&song(&[(0, [0x90, 60, 100, 0]), (300, [0x90, 62, 100, 0])]));
+    let control = player.control();
+    let playback = player.spawn(port);
+
+    sent_until(&backend, output, |sent| sent.contains(&note_on(60)));
+    control.pause();
+    assert_eq!(control.state(), PlayState::Paused);
+    // the sounding note is turned off, followed by sustain off and all notes off
+    let sent = sent_until(&backend, output, |sent| sent.len() >= 33);
+    assert_eq!(sent[0], note_off(60));
+    assert_eq!(sent[32], MidiMessage::from([0xBF, 123, 0, 0]));
+
+    thread::sleep(Duration::from_millis(400));
+    assert!(backend.sent(output).is_empty());
+
+    control.resume();
+    sent_until(&backend, output, |sent| sent.contains(&note_on(62)));
+    control.stop();
+    playback.join().unwrap();
+}
+
+#[test]
+fn test_seek() {
+    let (backend, output, context) = setup();
+    let port = context
+        .output_port(context.device(output).unwrap(), 1024)
+        .unwrap();
+    let player = Player::new(&song(&[
+        (0, [0x90, 60, 100, 0]),
+        (1000, [0x90, 61, 100, 0]),
+        (2000, [0x90, 62, 100, 0]),
+    ]));
+    let control = player.control();
+    let playback = player.spawn(port);
+
+    sent_until(&backend, output, |sent| sent.contains(&note_on(60)));
+    control.seek(Duration::from_millis(1500));
+    let sent = sent_until(&backend, output, |sent| sent.contains(&note_on(62)));
+    assert_eq!(sent[0], note_off(60));
+    assert!(!sent.contains(&note_on(61)));
+    assert!(control.position() >= Duration::from_millis(2000));
+    control.stop();
+    playback.join().unwrap();
+}
+
+#[test]
+fn test_loop() {
+    let (backend, output, context) = setup();
+    let port = context
+        .output_port(context.device(output).unwrap(), 1024)
+        .unwrap();
+    let player = Player::new(&song(&[
+        (0, [0x90, 60, 100, 0]),
+        (50, [0x90, 61, 100, 0]),
+        (100, [0x90, 62, 100, 0]),
+    ]));
+    let control = player.control();
+    control.set_loop(Some(Duration::from_millis(40)..Duration::from_millis(90)));
+    let playback = player.spawn(port);
+
+    let sent = sent_until(&backend, output, |sent| {
+        sent.iter()
+            .filter(|&&message| message == note_on(61))
+            .count()
+            >= 3
+    });
+    assert_eq!(
+        sent.iter()
+            .filter(|&&message| message == note_on(60))
+            .count(),
+        1
+    );
+    assert!(!sent.contains(&note_on(62)));
+    let position = control.position();
+    assert!(position >= Duration::from_millis(40) && position < Duration::from_millis(90));
+    control.stop();
+    playback.join().unwrap();
+}
+
+#[test]
+fn test_stop() {
+    let (backend, output, context) = setup();
+    let port = context
+        .output_port(context.device(output).unwrap(), 1024)
+        .unwrap();
+    let player = Player::new(&song(&[
+        (0, [0x90, 60, 100, 0]),
+        (0, [0x91, 64, 100, 0]),
+        (10_000, [0x80, 60, 0, 0]),
+    ]));
+    let control = player.control();
+    let playback = player.spawn(port);
+
+    sent_until(&backend, output, |sent| sent.len() >= 2);
+    control.stop();
+    let port = playback.join().unwrap();
+    assert_eq!(control.state(), PlayState::Stopped);
+    let sent = backend.sent(output);
+    assert_eq!(sent.len(), 34);
+    assert_eq!(sent[0].message, note_off(60));
+    assert_eq!(sent[1].message, MidiMessage::from([0x81, 64, 0, 0]));
+    assert!(sent[2..]
+        .iter()
+        .all(|event| event.message.status & 0xF0 == 0xB0));
+
+    // the port is returned to the caller
+    drop(port);
+    assert!(!context.device(output).unwrap().is_opened());
+}
+
+#[test]
+fn test_latency() {
+    let (backend, output, context) = setup();
+    // far from PortTime, so timestamps of the wrong clock aren't delivered in time
+    let start = Instant::now();
+    let time_source = move || 1_000_000 + start.elapsed().as_millis() as u32;
+    let options = OutputPortOptions::new(1024)
+        .with_latency(20)
+        .with_time_source(time_source);
+    let port = context
+        .output_port_with(context.device(output).unwrap(), options)
+        .unwrap();
+    let player = Player::new(&song(&[(0, [0x90, 60, 100, 0]), (50, [0x90, 62, 100, 0])]));
+    let playback = player.spawn(port);
+    let port = playback.join().unwrap();
+    assert_eq!(port.time() / 1_000_000, 1);
+    // sends the events that are still scheduled
+    port.close().unwrap();
+
+    // the events were scheduled with timestamps of the port's clock
+    let sent = backend.sent(output);
+    assert_eq!(sent[0].message, note_on(60));
+    assert_eq!(sent[1].message, note_on(62));
+    assert!(sent[0].timestamp >= 1_000_000);
+    // 50ms apart, give or take the rounding of the clocks
+    assert!(sent[1].timestamp - sent[0].timestamp >= 45);
+}
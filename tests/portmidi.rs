@@ -115,6 +115,30 @@ fn test_threads() {
     }
 }
 
+#[test]
+fn test_contexts() {
+    let ci = rci::Ci::new();
+    if ci.is_none() {
+        let shared = portmidi::PortMidi::shared().unwrap();
+        let other = portmidi::PortMidi::new().unwrap();
+        let mut out_port = other.default_output_port(1024).unwrap();
+        // dropping a context must not terminate PortMidi under the other one
+        drop(shared);
+        out_port
+            .write_message(portmidi::MidiMessage::from([0x90, 60, 127, 0]))
+            .unwrap();
+        let shared = portmidi::PortMidi::shared().unwrap();
+        drop(other);
+        out_port
+            .write_message(portmidi::MidiMessage::from([0x80, 60, 0, 0]))
+            .unwrap();
+        assert_eq!(
+            shared.device_count(),
+            portmidi::PortMidi::shared().unwrap().device_count()
+        );
+    }
+}
+
 #[test]
 fn test_types() {
     let message = portmidi::MidiMessage::from(0x007F3C81);
@@ -125,3 +149,131 @@ fn test_types() {
     // Velocity: 127
     assert_eq!(message.data2, 127);
 }
+
+#[test]
+fn test_messages() {
+    use portmidi::{ChannelMessage, Message, MessageError, MidiMessage, SystemMessage};
+    use std::convert::TryFrom;
+
+    let raw = MidiMessage::from(0x007F3C91);
+    let note_on = Message::try_from(raw).unwrap();
+    assert_eq!(
+        note_on,
+        Message::Channel(ChannelMessage::NoteOn {
+            channel: 1,
+            key: 60,
+            velocity: 127,
+        })
+    );
+    assert_eq!(MidiMessage::from(note_on), raw);
+
+    let bend = ChannelMessage::pitch_bend(15, 0x2001).unwrap();
+    let raw = MidiMessage::from(bend);
+    assert_eq!(raw.status, 0xEF);
+    assert_eq!((raw.data1, raw.data2), (0x01, 0x40));
+    assert_eq!(ChannelMessage::try_from(raw), Ok(bend));
+
+    let clock = MidiMessage::from(SystemMessage::Clock);
+    assert_eq!(
+        Message::try_from(clock),
+        Ok(Message::System(SystemMessage::Clock))
+    );
+
+    assert_eq!(
+        ChannelMessage::note_on(16, 60, 127),
+        Err(MessageError::ChannelOutOfRange(16))
+    );
+    assert_eq!(
+        ChannelMessage::control_change(0, 7, 128),
+        Err(MessageError::DataOutOfRange(128))
+    );
+    assert_eq!(
+        Message::try_from(MidiMessage::from([0xF0, 0x7E, 0x7F, 0x06])),
+        Err(MessageError::InvalidStatus(0xF0))
+    );
+    assert_eq!(
+        Message::try_from(MidiMessage::from([0xC0, 0x05, 0x01, 0x00])),
+        Err(MessageError::UnexpectedData(0x01))
+    );
+}
+
+#[test]
+fn test_filters() {
+    use portmidi::{ChannelMask, InputFilter};
+
+    assert_eq!(InputFilter::default(), InputFilter::ACTIVE);
+    let filter = InputFilter::ACTIVE | InputFilter::CLOCK | InputFilter::SYSEX;
+    assert!(filter.contains(InputFilter::CLOCK));
+    assert!(!filter.contains(InputFilter::NOTE));
+    assert_eq!(
+        filter - InputFilter::CLOCK,
+        InputFilter::ACTIVE | InputFilter::SYSEX
+    );
+    assert!(InputFilter::REALTIME.contains(filter));
+
+    assert_eq!(ChannelMask::default(), ChannelMask::ALL);
+    let mask = ChannelMask::channels(vec![0, 9]);
+    assert_eq!(mask.bits(), 0b10_0000_0001);
+    assert!(mask.contains(9));
+    assert!(!mask.without(9).contains(9));
+    assert_eq!(ChannelMask::channel(16), ChannelMask::NONE);
+}
+
+#[test]
+fn test_sysex_assembler() {
+    use portmidi::{Error, InputMessage, MidiEvent, MidiMessage, SysExAssembler, SysExError};
+
+    fn event(raw: [u8; 4], timestamp: u32) -> MidiEvent {
+        MidiEvent {
+            message: MidiMessage::from(raw),
+            timestamp,
+        }
+    }
+
+    let mut assembler = SysExAssembler::new(16);
+    let mut output = Vec::new();
+    assembler.push(event([0xF0, 0x7E, 0x7F, 0x06], 10), &mut output);
+    // interleaved clock
+    assembler.push(event([0xF8, 0x00, 0x00, 0x00], 11), &mut output);
+    assembler.push(event([0x01, 0xF7, 0x00, 0x00], 12), &mut output);
+    assembler.push(event([0x90, 60, 100, 0x00], 13), &mut output);
+    assert_eq!(output.len(), 3);
+    assert_eq!(
+        output[0],
+        Ok(InputMessage::Event(event([0xF8, 0, 0, 0], 11)))
+    );
+    match output[1] {
+        Ok(InputMessage::SysEx(ref sysex)) => {
+            assert_eq!(sysex.timestamp, 10);
+            assert_eq!(sysex.data, vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        }
+        ref other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(
+        output[2],
+        Ok(InputMessage::Event(event([0x90, 60, 100, 0], 13)))
+    );
+
+    // aborted by a note on
+    output.clear();
+    assembler.push(event([0xF0, 0x01, 0x02, 0x03], 20), &mut output);
+    assembler.push(event([0x90, 60, 100, 0x00], 21), &mut output);
+    assert_eq!(output[0], Err(Error::SysEx(SysExError::Aborted { len: 4 })));
+    assert_eq!(
+        output[1],
+        Ok(InputMessage::Event(event([0x90, 60, 100, 0], 21)))
+    );
+
+    // longer than 16 bytes
+    output.clear();
+    assembler.push(event([0xF0, 0x01, 0x02, 0x03], 30), &mut output);
+    for _ in 0..4 {
+        assembler.push(event([0x01, 0x02, 0x03, 0x04], 31), &mut output);
+    }
+    assembler.push(event([0x01, 0xF7, 0x00, 0x00], 32), &mut output);
+    assert_eq!(
+        output,
+        vec![Err(Error::SysEx(SysExError::TooLong { max_len: 16 }))]
+    );
+    assert!(!assembler.is_receiving());
+}
@@ -0,0 +1,288 @@
+extern crate portmidi;
+
+use portmidi::smf::{EventKind, Format, MetaEvent, Smf, SmfError, Timing, Track, TrackEvent};
+use portmidi::MidiMessage;
+use std::fs;
+
+const FIXTURES: [&str; 3] = [
+    "tests/fixtures/format0.mid",
+    "tests/fixtures/format1.mid",
+    "tests/fixtures/format2.mid",
+];
+
+#[test]
+fn test_round_trip() {
+    for path in FIXTURES.iter() {
+        let data = fs::read(path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        let written = smf.to_bytes().unwrap();
+        assert_eq!(written, data, "{} differs after writing", path);
+        assert_eq!(Smf::parse(&written).unwrap(), smf);
+    }
+}
+
+#[test]
+fn test_format0() {
+    let smf = Smf::load("tests/fixtures/format0.mid").unwrap();
+    assert_eq!(smf.format, Format::SingleTrack);
+    assert_eq!(smf.timing, Timing::Metrical(480));
+    assert_eq!(smf.tracks.len(), 1);
+
+    let track = &smf.tracks[0];
+    assert_eq!(track.name(), Some(&b"Twinkle"[..]));
+    let events = &track.events;
+    assert_eq!(
+        events[1].kind,
+        EventKind::Meta(MetaEvent::TimeSignature {
+            numerator: 4,
+            denominator: 2,
+            clocks_per_click: 24,
+            notated_32nds_per_quarter: 8,
+        })
+    );
+    assert_eq!(
+        events[2].kind,
+        EventKind::Meta(MetaEvent::KeySignature {
+            sharps: -2,
+            minor: false,
+        })
+    );
+    assert_eq!(events[3].kind, EventKind::Meta(MetaEvent::Tempo(500_000)));
+    assert_eq!(
+        events[4].kind,
+        EventKind::SysEx(vec![0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7])
+    );
+    assert_eq!(
+        events[5].kind,
+        EventKind::Midi(MidiMessage::from([0xC0, 5, 0, 0]))
+    );
+    // running status
+    assert_eq!(events[7].delta, 480);
+    assert_eq!(
+        events[7].kind,
+        EventKind::Midi(MidiMessage::from([0x90, 60, 0, 0]))
+    );
+    assert_eq!(events[11].delta, 200_000);
+    assert_eq!(
+        events.last().unwrap().kind,
+        EventKind::Meta(MetaEvent::EndOfTrack)
+    );
+}
+
+#[test]
+fn test_format1_and_format2() {
+    let smf = Smf::load("tests/fixtures/format1.mid").unwrap();
+    assert_eq!(smf.format, Format::MultiTrack);
+    assert_eq!(smf.timing, Timing::Metrical(96));
+    let names: Vec<_> = smf
+        .tracks
+        .iter()
+        .map(|track| track.name().unwrap())
+        .collect();
+    assert_eq!(names, vec![&b"Conductor"[..], &b"Melody"[..], &b"Bass"[..]]);
+    assert_eq!(
+        smf.tracks[0].events[3],
+        TrackEvent::new(960, EventKind::Meta(MetaEvent::Tempo(333_333)))
+    );
+
+    let smf = Smf::load("tests/fixtures/format2.mid").unwrap();
+    assert_eq!(smf.format, Format::MultiSong);
+    assert_eq!(
+        smf.timing,
+        Timing::Timecode {
+            fps: 25,
+            ticks_per_frame: 40,
+        }
+    );
+    assert_eq!(
+        smf.tracks[1].events[2].kind,
+        EventKind::Escape(vec![0x43, 0x12])
+    );
+}
+
+#[test]
+fn test_write() {
+    let mut smf = Smf::new(Format::SingleTrack, Timing::Metrical(96));
+    let mut track = Track::new();
+    track.events.push(TrackEvent::new(
+        0,
+        EventKind::Midi(MidiMessage::from([0x90, 60, 100, 0])),
+    ));
+    track.events.push(TrackEvent::new(
+        0x0FFF_FFFF,
+        EventKind::Midi(MidiMessage::from([0x80, 60, 0, 0])),
+    ));
+    smf.tracks.push(track);
+
+    let data = smf.to_bytes().unwrap();
+    let parsed = Smf::parse(&data).unwrap();
+    assert_eq!(parsed.tracks[0].events[..2], smf.tracks[0].events[..]);
+    // the end of track event is appended
+    assert_eq!(
+        parsed.tracks[0].events[2],
+        TrackEvent::new(0, EventKind::Meta(MetaEvent::EndOfTrack))
+    );
+
+    // deltas that don't fit into 28 bits are rejected instead of being truncated
+    smf.tracks[0].events[1].delta = 0x1000_0000;
+    match smf.to_bytes() {
+        Err(SmfError::InvalidVlq) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    smf.tracks[0].events[1].delta = 0;
+
+    // invalid channel messages are rejected instead of being masked
+    smf.tracks[0].events[1].kind = EventKind::Midi(MidiMessage::from([0x3C, 60, 0, 0]));
+    match smf.to_bytes() {
+        Err(SmfError::InvalidStatus(0x3C)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    smf.tracks[0].events[1].kind = EventKind::Midi(MidiMessage::from([0x80, 60, 0x80, 0]));
+    match smf.to_bytes() {
+        Err(SmfError::InvalidDataByte(0x80)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    smf.tracks[0].events.truncate(1);
+
+    smf.timing = Timing::Metrical(0x8000);
+    match smf.to_bytes() {
+        Err(SmfError::InvalidDivision(0x8000)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    smf.timing = Timing::Metrical(96);
+
+    smf.tracks.push(Track::new());
+    match smf.to_bytes() {
+        Err(SmfError::InvalidTrackCount(2)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let mut smf = Smf::new(Format::MultiTrack, Timing::Metrical(96));
+    smf.tracks.resize(0x1_0000, Track::new());
+    match smf.to_bytes() {
+        Err(SmfError::InvalidTrackCount(0x1_0000)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    match Smf::parse(b"RIFF\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60") {
+        Err(SmfError::InvalidHeader) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let mut data = fs::read(FIXTURES[0]).unwrap();
+    data.truncate(40);
+    match Smf::parse(&data) {
+        Err(SmfError::UnexpectedEof) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    // a data byte without running status
+    let data = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk\x00\x00\x00\x03\x00\x3c\x40";
+    match Smf::parse(data) {
+        Err(SmfError::MissingStatus) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    // a note on with a velocity above 127
+    let data = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk\x00\x00\x00\x04\x00\x90\x3c\xc0";
+    match Smf::parse(data) {
+        Err(SmfError::InvalidDataByte(0xC0)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    // a SysEx event longer than the track is rejected before allocating
+    let data = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk\x00\x00\x00\x07\x00\xf0\xff\xff\xff\x7f\xf7";
+    match Smf::parse(data) {
+        Err(SmfError::UnexpectedEof) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    // a format 0 file with two tracks
+    let mut data = fs::read(FIXTURES[0]).unwrap();
+    data[11] = 2;
+    match Smf::parse(&data) {
+        Err(SmfError::InvalidTrackCount(2)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_recorder() {
+    use portmidi::{MidiEvent, RecordFormat, Recorder};
+
+    fn event(raw: [u8; 4], timestamp: u32) -> MidiEvent {
+        MidiEvent {
+            message: MidiMessage::from(raw),
+            timestamp,
+        }
+    }
+
+    // 120 bpm at 480 ppq: one tick per 1.04 ms
+    let mut recorder = Recorder::new(480, 500_000).with_format(RecordFormat::PerChannel);
+    recorder.start_at(1000);
+    recorder.record(event([0x90, 60, 100, 0], 1000)).unwrap();
+    recorder.record(event([0xF8, 0, 0, 0], 1100)).unwrap();
+    recorder.record(event([0x91, 64, 100, 0], 1250)).unwrap();
+    recorder
+        .record(event([0xF0, 0x7E, 0x7F, 0x06], 1400))
+        .unwrap();
+    recorder.record(event([0x01, 0xF7, 0, 0], 1401)).unwrap();
+    recorder.record(event([0x80, 60, 0, 0], 1500)).unwrap();
+    assert_eq!(recorder.len(), 4);
+
+    let smf = recorder.stop();
+    assert_eq!(smf.format, Format::MultiTrack);
+    assert_eq!(smf.timing, Timing::Metrical(480));
+    assert_eq!(smf.tracks.len(), 3);
+    assert_eq!(
+        smf.tracks[0].events,
+        vec![
+            TrackEvent::new(0, EventKind::Meta(MetaEvent::Tempo(500_000))),
+            TrackEvent::new(
+                384,
+                EventKind::SysEx(vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7])
+            ),
+            TrackEvent::new(96, EventKind::Meta(MetaEvent::EndOfTrack)),
+        ]
+    );
+    assert_eq!(smf.tracks[1].name(), Some(&b"Channel 1"[..]));
+    assert_eq!(
+        smf.tracks[1].events[1..],
+        [
+            TrackEvent::new(0, EventKind::Midi(MidiMessage::from([0x90, 60, 100, 0]))),
+            TrackEvent::new(480, EventKind::Midi(MidiMessage::from([0x80, 60, 0, 0]))),
+            TrackEvent::new(0, EventKind::Meta(MetaEvent::EndOfTrack)),
+        ]
+    );
+    assert_eq!(smf.tracks[2].name(), Some(&b"Channel 2"[..]));
+    assert_eq!(
+        smf.tracks[2].events[1],
+        TrackEvent::new(240, EventKind::Midi(MidiMessage::from([0x91, 64, 100, 0])))
+    );
+    assert_eq!(Smf::parse(&smf.to_bytes().unwrap()).unwrap(), smf);
+}
+
+#[test]
+fn test_player_duration() {
+    use portmidi::Player;
+    use std::time::Duration;
+
+    let mut conductor = Track::new();
+    conductor.events = vec![
+        TrackEvent::new(0, EventKind::Meta(MetaEvent::Tempo(500_000))),
+        TrackEvent::new(480, EventKind::Meta(MetaEvent::Tempo(250_000))),
+        TrackEvent::new(0, EventKind::Meta(MetaEvent::EndOfTrack)),
+    ];
+    let mut notes = Track::new();
+    notes.events = vec![
+        TrackEvent::new(0, EventKind::Midi(MidiMessage::from([0x90, 60, 100, 0]))),
+        TrackEvent::new(960, EventKind::Midi(MidiMessage::from([0x80, 60, 0, 0]))),
+        TrackEvent::new(0, EventKind::Meta(MetaEvent::EndOfTrack)),
+    ];
+    let mut smf = Smf::new(Format::MultiTrack, Timing::Metrical(480));
+    smf.tracks = vec![conductor, notes];
+    // one quarter note at 120 bpm and one at 240 bpm
+    assert_eq!(Player::new(&smf).duration(), Duration::from_millis(750));
+
+    // format 2 tracks are played one after the other at the default tempo
+    smf.format = Format::MultiSong;
+    assert_eq!(Player::new(&smf).duration(), Duration::from_millis(1500));
+}
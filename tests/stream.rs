@@ -0,0 +1,115 @@
+#![cfg(feature = "async")]
+extern crate futures;
+extern crate portmidi;
+
+use futures::executor::{self, block_on_stream};
+use futures::StreamExt;
+use portmidi::backend::LoopbackBackend;
+use portmidi::{Error, InputPort, MidiEvent, MidiMessage, PmError, PortMidi};
+use std::thread;
+use std::time::Duration;
+
+const WAKE_INTERVAL: Duration = Duration::from_millis(1);
+// long enough for the stream thread to wake up and read
+const SETTLE: Duration = Duration::from_millis(100);
+
+fn event(note: u8) -> MidiEvent {
+    MidiEvent {
+        message: MidiMessage::from([0x90, note, 100, 0]),
+        timestamp: u32::from(note),
+    }
+}
+
+fn open(backend: &LoopbackBackend) -> (PortMidi, InputPort) {
+    let (input, _) = backend.add_device_pair("Loop");
+    let context = PortMidi::with_backend(backend.clone()).unwrap();
+    let in_port = context
+        .input_port(context.device(input).unwrap(), 16)
+        .unwrap();
+    (context, in_port)
+}
+
+#[test]
+fn test_stream_delivery() {
+    let backend = LoopbackBackend::new();
+    let (_context, in_port) = open(&backend);
+    let id = in_port.device().id();
+    let stream = in_port.into_stream_with(WAKE_INTERVAL, 16);
+    let events: Vec<_> = (60..64).map(event).collect();
+    backend.send(id, &events).unwrap();
+    let received: Vec<_> = block_on_stream(stream)
+        .take(events.len())
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(received, events);
+}
+
+#[test]
+fn test_stream_error() {
+    let backend = LoopbackBackend::new();
+    let (_context, in_port) = open(&backend);
+    let mut stream = block_on_stream(in_port.into_stream_with(WAKE_INTERVAL, 16));
+    backend.fail_next(PmError::PmBufferOverflow);
+    assert_eq!(
+        stream.next().unwrap(),
+        Err(Error::PortMidi(PmError::PmBufferOverflow))
+    );
+    // the stream ends after the first error
+    assert_eq!(stream.next(), None);
+}
+
+#[test]
+fn test_stream_backpressure() {
+    let backend = LoopbackBackend::new();
+    let (_context, in_port) = open(&backend);
+    let id = in_port.device().id();
+    let stream = in_port.into_stream_with(WAKE_INTERVAL, 2);
+
+    // the thread queues two events and waits for the consumer with the others
+    backend
+        .send(id, &[event(60), event(61), event(62)])
+        .unwrap();
+    thread::sleep(SETTLE);
+    // so it stops reading and later events stay in the port
+    backend.send(id, &[event(70), event(71)]).unwrap();
+    thread::sleep(SETTLE);
+    let in_port = stream.stop();
+    assert_eq!(
+        in_port.read_n(16).unwrap(),
+        Some(vec![event(70), event(71)])
+    );
+}
+
+#[test]
+fn test_stream_drop() {
+    let backend = LoopbackBackend::new();
+    let (context, in_port) = open(&backend);
+    let id = in_port.device().id();
+    let stream = in_port.into_stream_with(WAKE_INTERVAL, 1);
+    assert!(context.device(id).unwrap().is_opened());
+
+    // dropping joins the thread, even while it waits for the consumer,
+    // and closes the port
+    backend
+        .send(id, &[event(60), event(61), event(62)])
+        .unwrap();
+    thread::sleep(SETTLE);
+    drop(stream);
+    assert!(!context.device(id).unwrap().is_opened());
+}
+
+#[test]
+fn test_stream_wake_interval() {
+    let backend = LoopbackBackend::new();
+    let (_context, in_port) = open(&backend);
+    let id = in_port.device().id();
+    let mut stream = in_port.into_stream_with(WAKE_INTERVAL, 16);
+    assert_eq!(stream.wake_interval(), WAKE_INTERVAL);
+
+    stream.set_wake_interval(Duration::from_millis(20));
+    assert_eq!(stream.wake_interval(), Duration::from_millis(20));
+    // events are still delivered at the new interval
+    thread::sleep(SETTLE);
+    backend.send(id, &[event(60)]).unwrap();
+    assert_eq!(executor::block_on(stream.next()), Some(Ok(event(60))));
+}